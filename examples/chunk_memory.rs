@@ -0,0 +1,53 @@
+//! Measures how much memory palette compressed chunks take up compared to storing every block directly,
+//! along with how long it takes to read and write their blocks.
+//! Run with `cargo run --release --example chunk_memory`
+
+use std::time::Instant;
+
+use freecraft::block::{BlockId, Chunk, LocalPos, CHUNK_SIZE, CHUNK_SIZE_AXIS};
+use freecraft::registry::BlockRegistry;
+use freecraft::terrain::{HeightmapGenerator, TerrainGenerator};
+use glam::IVec3;
+
+fn main() {
+    let registry = BlockRegistry::global();
+    let stone = registry.id("stone").unwrap();
+    let block_ids: Vec<BlockId> = registry.iter().map(|(id, _)| id).collect();
+
+    let air: Chunk = Chunk::new(IVec3::ZERO);
+    let solid: Chunk = Chunk::filled(IVec3::ZERO, stone);
+
+    // A chunk with hills, caves and ores in it
+    let generator = HeightmapGenerator::new(0);
+    let terrain = generator.generate(IVec3::new(0, -1, 0));
+
+    // Every block is random, which is the worst case for the palette
+    fastrand::seed(0);
+    let mut random: Chunk = Chunk::new(IVec3::ZERO);
+    LocalPos::all::<CHUNK_SIZE_AXIS>()
+        .for_each(|local_pos| random.set_block(local_pos, block_ids[fastrand::usize(0..block_ids.len())]));
+
+    let unpacked_size = CHUNK_SIZE * std::mem::size_of::<BlockId>();
+    println!("Storing every block directly takes {unpacked_size} bytes per chunk");
+
+    for (name, chunk) in [("air", &air), ("solid", &solid), ("terrain", &terrain), ("random", &random)] {
+        // Chunks haven't been meshed, so this is just the blocks and the chunk itself
+        let memory_usage = chunk.memory_usage();
+
+        let start = Instant::now();
+        let solid_blocks = LocalPos::all::<CHUNK_SIZE_AXIS>().filter(|local_pos| chunk.get_block(*local_pos) != BlockId::AIR).count();
+        let read_time = start.elapsed();
+
+        let mut copy: Chunk = Chunk::new(IVec3::ZERO);
+        let start = Instant::now();
+        LocalPos::all::<CHUNK_SIZE_AXIS>().for_each(|local_pos| copy.set_block(local_pos, chunk.get_block(local_pos)));
+        let write_time = start.elapsed();
+
+        println!(
+            "{name:>7}: {memory_usage} bytes, {} palette entries, {solid_blocks} solid blocks, {read_time:?} to read, {write_time:?} to copy",
+            chunk.palette().len(),
+        );
+
+    }
+
+}
@@ -0,0 +1,54 @@
+//! Compares the size of the meshes and how long it takes each mesher to build them.
+//! Run with `cargo run --release --example mesh_stats`
+
+use std::time::Instant;
+
+use freecraft::block::{BlockId, Chunk, LocalPos, CHUNK_SIZE_AXIS};
+use freecraft::lighting::ChunkLight;
+use freecraft::mesh::Mesher;
+use freecraft::registry::BlockRegistry;
+use glam::IVec3;
+
+const ITERATIONS: u32 = 20;
+
+fn main() {
+    let registry = BlockRegistry::global();
+    let dirt = registry.id("dirt").unwrap();
+    let stone = registry.id("stone").unwrap();
+
+    let solid: Chunk = Chunk::filled(IVec3::ZERO, dirt);
+
+    // The bottom half is stone and the top half is air
+    let mut half: Chunk = Chunk::new(IVec3::ZERO);
+    LocalPos::all::<CHUNK_SIZE_AXIS>()
+        .filter(|local_pos| (local_pos.y as usize) < CHUNK_SIZE_AXIS / 2)
+        .for_each(|local_pos| half.set_block(local_pos, stone));
+
+    // Every block is randomly air, dirt or stone, which is close to the worst case for greedy meshing
+    fastrand::seed(0);
+    let mut random: Chunk = Chunk::new(IVec3::ZERO);
+    LocalPos::all::<CHUNK_SIZE_AXIS>()
+        .for_each(|local_pos| random.set_block(local_pos, [BlockId::AIR, dirt, stone][fastrand::usize(0..3)]));
+
+    for (name, chunk) in [("solid", &solid), ("half", &half), ("random", &random)] {
+        // The chunks aren't in a world, so they're meshed in the dark
+        let light = ChunkLight::from_chunk(chunk);
+
+        for mesher in [Mesher::Naive, Mesher::Greedy] {
+            let start = Instant::now();
+
+            for _ in 0..ITERATIONS {
+                mesher.mesh(chunk, &light, true);
+
+            }
+
+            let time_per_mesh = start.elapsed() / ITERATIONS;
+            let stats = mesher.mesh(chunk, &light, true).stats();
+
+            println!("{name:>6} {mesher:?}: {} vertices, {} indices, {time_per_mesh:?} per mesh", stats.vertices, stats.indices);
+
+        }
+
+    }
+
+}
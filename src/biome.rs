@@ -0,0 +1,98 @@
+use crate::noise::{hash_u64, Perlin};
+
+/// The kinds of land a column of terrain can belong to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Biome {
+    Plains,
+    Desert,
+    Mountains,
+    Snow,
+
+}
+
+impl Biome {
+    pub const ALL: [Biome; 4] = [Biome::Plains, Biome::Desert, Biome::Mountains, Biome::Snow];
+
+    /// Picks the biome for a climate, where temperature and humidity are both roughly -1 to 1
+    pub fn from_climate(temperature: f64, humidity: f64) -> Self {
+        if temperature < -0.2 {
+            Biome::Snow
+
+        } else if temperature > 0.15 && humidity < 0.0 {
+            Biome::Desert
+
+        } else if humidity > 0.2 {
+            Biome::Mountains
+
+        } else {
+            Biome::Plains
+
+        }
+
+    }
+
+    /// The block height the biome's terrain varies around
+    pub fn base_height(self) -> f64 {
+        match self {
+            Biome::Plains => 0.0,
+            Biome::Desert => 2.0,
+            Biome::Mountains => 16.0,
+            Biome::Snow => 4.0,
+
+        }
+
+    }
+
+    /// How many blocks above or below the base height the biome's terrain can reach
+    pub fn amplitude(self) -> f64 {
+        match self {
+            Biome::Plains => 12.0,
+            Biome::Desert => 8.0,
+            Biome::Mountains => 56.0,
+            Biome::Snow => 20.0,
+
+        }
+
+    }
+
+}
+
+/// Decides which biome each column of the world is in, from temperature and humidity noise
+#[derive(Clone)]
+pub struct BiomeMap {
+    temperature: Perlin,
+    humidity: Perlin,
+    /// How many blocks wide climate zones are
+    pub scale: f64,
+
+}
+
+impl BiomeMap {
+    pub fn new(seed: u64) -> Self {
+        // Each noise gets its own seed, so temperature and humidity don't follow each other
+        Self {
+            temperature: Perlin::new(hash_u64(seed ^ 0x7E3A)),
+            humidity: Perlin::new(hash_u64(seed ^ 0x4D1C)),
+            scale: 384.0,
+
+        }
+
+    }
+
+    pub fn temperature(&self, x: i32, z: i32) -> f64 {
+        self.temperature.fbm2(x as f64 / self.scale, z as f64 / self.scale, 2)
+
+    }
+
+    pub fn humidity(&self, x: i32, z: i32) -> f64 {
+        self.humidity.fbm2(x as f64 / self.scale, z as f64 / self.scale, 2)
+
+    }
+
+    /// The biome of the column at the given block x and z
+    pub fn biome(&self, x: i32, z: i32) -> Biome {
+        Biome::from_climate(self.temperature(x, z), self.humidity(x, z))
+
+    }
+
+}
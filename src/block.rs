@@ -1,22 +1,64 @@
-use std::{cmp::Ordering, ops::Add};
+use glam::{IVec3, UVec3, Vec3};
 
-use glam::Vec3;
+use crate::mesh::{ChunkMesh, Mesher, UNOCCLUDED};
+use crate::lighting::{ChunkLight, LightLevel, MAX_LIGHT};
+use crate::palette::PalettedBlocks;
+use crate::physics::Aabb;
+use crate::registry::{BlockProperties, BlockRegistry};
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Vertex {
     position: Vec3,
+    // Goes from 0 to 1 across a block face, and is mapped to the face's texture in the atlas by the shader
     tex_coords: [f32; 2],
+    // The index of the texture in the block registry
+    texture: u32,
+    // Which way the face the vertex is part of points, for lighting
+    normal: Vec3,
+    // The sky light and block light shining on the face, from 0 to 1
+    light: [f32; 2],
+    // How much of the light reaches the vertex past the blocks around it, from 0 to 1
+    ambient_occlusion: f32,
 }
 
 impl Vertex {
+    pub const fn new(position: Vec3, tex_coords: [f32; 2], texture: u32, normal: Vec3, light: LightLevel, ambient_occlusion: u8) -> Self {
+        Self {
+            position,
+            tex_coords,
+            texture,
+            normal,
+            light: Self::light_levels(light),
+            ambient_occlusion: ambient_occlusion as f32 / UNOCCLUDED as f32,
+        }
+    }
+
     pub const fn zero() -> Self {
         Self {
             position: Vec3::ZERO,
             tex_coords: [0.0; 2],
+            texture: 0,
+            normal: Vec3::ZERO,
+            light: [0.0; 2],
+            ambient_occlusion: 1.0,
         }
     }
 
+    pub const fn position(&self) -> Vec3 {
+        self.position
+    }
+
+    /// Returns a copy with the given ambient occlusion, from 0 for fully occluded to UNOCCLUDED
+    pub const fn with_ambient_occlusion(mut self, ambient_occlusion: u8) -> Self {
+        self.ambient_occlusion = ambient_occlusion as f32 / UNOCCLUDED as f32;
+        self
+    }
+
+    const fn light_levels(light: LightLevel) -> [f32; 2] {
+        [light.sky() as f32 / MAX_LIGHT as f32, light.block() as f32 / MAX_LIGHT as f32]
+    }
+
     pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
@@ -31,101 +73,268 @@ impl Vertex {
                     offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
                     shader_location: 1,
                     format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Uint32,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 6]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 9]>() as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 11]>() as wgpu::BufferAddress,
+                    shader_location: 5,
+                    format: wgpu::VertexFormat::Float32,
                 }
             ]
         }
     }
 }
 
-const BLOCK_SIZE: f32 = 2.0;
-const VERTICES_PER_BLOCK: usize = 24;
-const INDICES_PER_BLOCK: usize = 36;
+pub const BLOCK_SIZE: f32 = 2.0;
+
+/// One of the six faces of a block, named after the direction it points in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Face {
+    ZPos,
+    ZNeg,
+    XPos,
+    XNeg,
+    YPos,
+    YNeg,
+
+}
+
+impl Face {
+    /// Every face, in the order their vertices are stored in a block's mesh
+    pub const ALL: [Face; 6] = [Face::ZPos, Face::ZNeg, Face::XPos, Face::XNeg, Face::YPos, Face::YNeg];
 
-struct BlockNeighbors {
-    z_pos: Option<u16>,
-    z_neg: Option<u16>,
+    pub const fn normal(self) -> IVec3 {
+        match self {
+            Face::ZPos => IVec3::Z,
+            Face::ZNeg => IVec3::NEG_Z,
+            Face::XPos => IVec3::X,
+            Face::XNeg => IVec3::NEG_X,
+            Face::YPos => IVec3::Y,
+            Face::YNeg => IVec3::NEG_Y,
 
-    y_pos: Option<u16>,
-    y_neg: Option<u16>,
+        }
 
-    x_pos: Option<u16>,
-    x_neg: Option<u16>,
+    }
+
+    /// The axis the face's normal is along, where x is 0, y is 1 and z is 2
+    pub const fn axis(self) -> usize {
+        match self {
+            Face::XPos | Face::XNeg => 0,
+            Face::YPos | Face::YNeg => 1,
+            Face::ZPos | Face::ZNeg => 2,
+
+        }
+
+    }
+
+    /// Whether the face points along the positive direction of its axis
+    pub const fn is_positive(self) -> bool {
+        matches!(self, Face::XPos | Face::YPos | Face::ZPos)
+
+    }
+
+    pub const fn opposite(self) -> Face {
+        match self {
+            Face::ZPos => Face::ZNeg,
+            Face::ZNeg => Face::ZPos,
+            Face::XPos => Face::XNeg,
+            Face::XNeg => Face::XPos,
+            Face::YPos => Face::YNeg,
+            Face::YNeg => Face::YPos,
+
+        }
+
+    }
 
 }
 
-// Block locations are stored by their position within a chunk
-#[derive(Clone, Copy)]
-struct Block {
-    solid: bool,
-    block_num: u16,
+/// A block's position within a chunk, in blocks from the chunk's first block
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LocalPos {
+    pub x: u32,
+    pub y: u32,
+    pub z: u32,
 
 }
 
-impl Block {
-    const fn new(block_num: u16, solid: bool) -> Self {
-        Self { 
-            solid,
-            block_num,
+impl LocalPos {
+    pub const fn new(x: u32, y: u32, z: u32) -> Self {
+        Self { x, y, z }
+
+    }
+
+    /// Converts the position into its index in a chunk that is N blocks long on each axis
+    pub const fn to_index<const N: usize>(self) -> usize {
+        self.x as usize + N * (self.y as usize + N * self.z as usize)
+
+    }
+
+    /// The inverse of to_index
+    pub const fn from_index<const N: usize>(index: usize) -> Self {
+        Self {
+            x: (index % N) as u32,
+            y: ((index / N) % N) as u32,
+            z: (index / (N * N)) as u32,
 
         }
 
     }
 
-    fn as_vertices_z_pos(&self, block_pos: Vec3) -> [Vertex; 4] {
+    /// Whether the position is inside of a chunk that is N blocks long on each axis
+    pub const fn is_in_chunk<const N: usize>(self) -> bool {
+        (self.x as usize) < N && (self.y as usize) < N && (self.z as usize) < N
+
+    }
+
+    /// Every position in a chunk that is N blocks long on each axis, in index order
+    pub fn all<const N: usize>() -> impl Iterator<Item = LocalPos> {
+        (0..N.pow(3)).map(LocalPos::from_index::<N>)
+
+    }
+
+    /// The position next to this one in the direction of face, or None if it would be outside of the chunk
+    pub fn neighbor<const N: usize>(self, face: Face) -> Option<LocalPos> {
+        let neighbor = self.as_ivec3() + face.normal();
+
+        match neighbor.cmpge(IVec3::ZERO).all() && neighbor.cmplt(IVec3::splat(N as i32)).all() {
+            true => Some(neighbor.as_uvec3().into()),
+            false => None,
+
+        }
+
+    }
+
+    /// Every neighbor of this position that is inside of the chunk, along with the face it's touching
+    pub fn neighbors<const N: usize>(self) -> impl Iterator<Item = (Face, LocalPos)> {
+        Face::ALL.into_iter().filter_map(move |face| self.neighbor::<N>(face).map(|neighbor| (face, neighbor)))
+
+    }
+
+    pub const fn as_uvec3(self) -> UVec3 {
+        UVec3::new(self.x, self.y, self.z)
+
+    }
+
+    pub fn as_ivec3(self) -> IVec3 {
+        self.as_uvec3().as_ivec3()
+
+    }
+
+    /// The position of the block's center relative to the chunk's first block, in world units
+    pub fn rel_pos(self) -> Vec3 {
+        self.as_uvec3().as_vec3() * Vec3::splat(BLOCK_SIZE)
+
+    }
+
+}
+
+impl From<UVec3> for LocalPos {
+    fn from(pos: UVec3) -> Self {
+        Self::new(pos.x, pos.y, pos.z)
+
+    }
+
+}
+
+/// The type of a block, which indexes into the BlockRegistry
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BlockId(pub u16);
+
+impl BlockId {
+    pub const AIR: BlockId = BlockId(0);
+
+    pub fn properties(self) -> &'static BlockProperties {
+        BlockRegistry::global().get(self)
+
+    }
+
+    fn as_vertices_z_pos(&self, block_pos: Vec3, texture: u32, light: LightLevel) -> [Vertex; 4] {
+        let normal = Face::ZPos.normal().as_vec3();
+        let light = Vertex::light_levels(light);
+
         [
-            Vertex { position: block_pos + Vec3::from_array([-1.0, -1.0, 1.0]), tex_coords: [1.0, 1.0] },
-            Vertex { position: block_pos + Vec3::from_array([1.0, -1.0, 1.0]), tex_coords: [1.0, 0.0] },
-            Vertex { position: block_pos + Vec3::from_array([1.0, 1.0, 1.0]), tex_coords: [0.0, 0.0] },
-            Vertex { position: block_pos + Vec3::from_array([-1.0, 1.0, 1.0]), tex_coords: [0.0, 1.0] },
+            Vertex { position: block_pos + Vec3::from_array([-1.0, -1.0, 1.0]), tex_coords: [0.0, 1.0], texture, normal, light, ambient_occlusion: 1.0 },
+            Vertex { position: block_pos + Vec3::from_array([1.0, -1.0, 1.0]), tex_coords: [1.0, 1.0], texture, normal, light, ambient_occlusion: 1.0 },
+            Vertex { position: block_pos + Vec3::from_array([1.0, 1.0, 1.0]), tex_coords: [1.0, 0.0], texture, normal, light, ambient_occlusion: 1.0 },
+            Vertex { position: block_pos + Vec3::from_array([-1.0, 1.0, 1.0]), tex_coords: [0.0, 0.0], texture, normal, light, ambient_occlusion: 1.0 },
         ]
     }
 
-    fn as_vertices_z_neg(&self, block_pos: Vec3) -> [Vertex; 4] {
+    fn as_vertices_z_neg(&self, block_pos: Vec3, texture: u32, light: LightLevel) -> [Vertex; 4] {
+        let normal = Face::ZNeg.normal().as_vec3();
+        let light = Vertex::light_levels(light);
+
         [
-            Vertex { position: block_pos + Vec3::from_array([-1.0, 1.0, -1.0]), tex_coords: [1.0, 1.0] },
-            Vertex { position: block_pos + Vec3::from_array([1.0, 1.0, -1.0]), tex_coords: [1.0, 0.0] },
-            Vertex { position: block_pos + Vec3::from_array([1.0, -1.0, -1.0]), tex_coords: [0.0, 0.0] },
-            Vertex { position: block_pos + Vec3::from_array([-1.0, -1.0, -1.0]), tex_coords: [0.0, 1.0] },
+            Vertex { position: block_pos + Vec3::from_array([-1.0, 1.0, -1.0]), tex_coords: [1.0, 0.0], texture, normal, light, ambient_occlusion: 1.0 },
+            Vertex { position: block_pos + Vec3::from_array([1.0, 1.0, -1.0]), tex_coords: [0.0, 0.0], texture, normal, light, ambient_occlusion: 1.0 },
+            Vertex { position: block_pos + Vec3::from_array([1.0, -1.0, -1.0]), tex_coords: [0.0, 1.0], texture, normal, light, ambient_occlusion: 1.0 },
+            Vertex { position: block_pos + Vec3::from_array([-1.0, -1.0, -1.0]), tex_coords: [1.0, 1.0], texture, normal, light, ambient_occlusion: 1.0 },
         ]
     }
 
-    fn as_vertices_left(&self, block_pos: Vec3) -> [Vertex; 4] {
+    fn as_vertices_left(&self, block_pos: Vec3, texture: u32, light: LightLevel) -> [Vertex; 4] {
+        let normal = Face::XNeg.normal().as_vec3();
+        let light = Vertex::light_levels(light);
+
         [
-            Vertex { position: block_pos + Vec3::from_array([-1.0, -1.0, 1.0]), tex_coords: [1.0, 1.0] },
-            Vertex { position: block_pos + Vec3::from_array([-1.0, 1.0, 1.0]), tex_coords: [1.0, 0.0] },
-            Vertex { position: block_pos + Vec3::from_array([-1.0, 1.0, -1.0]), tex_coords: [0.0, 0.0] },
-            Vertex { position: block_pos + Vec3::from_array([-1.0, -1.0, -1.0]), tex_coords: [0.0, 1.0] },
+            Vertex { position: block_pos + Vec3::from_array([-1.0, -1.0, 1.0]), tex_coords: [1.0, 1.0], texture, normal, light, ambient_occlusion: 1.0 },
+            Vertex { position: block_pos + Vec3::from_array([-1.0, 1.0, 1.0]), tex_coords: [1.0, 0.0], texture, normal, light, ambient_occlusion: 1.0 },
+            Vertex { position: block_pos + Vec3::from_array([-1.0, 1.0, -1.0]), tex_coords: [0.0, 0.0], texture, normal, light, ambient_occlusion: 1.0 },
+            Vertex { position: block_pos + Vec3::from_array([-1.0, -1.0, -1.0]), tex_coords: [0.0, 1.0], texture, normal, light, ambient_occlusion: 1.0 },
         ]
     }
 
-    fn as_vertices_right(&self, block_pos: Vec3) -> [Vertex; 4] {
+    fn as_vertices_right(&self, block_pos: Vec3, texture: u32, light: LightLevel) -> [Vertex; 4] {
+        let normal = Face::XPos.normal().as_vec3();
+        let light = Vertex::light_levels(light);
+
         [
-            Vertex { position: block_pos + Vec3::from_array([1.0, -1.0, -1.0]), tex_coords: [1.0, 1.0] },
-            Vertex { position: block_pos + Vec3::from_array([1.0, 1.0, -1.0]), tex_coords: [1.0, 0.0] },
-            Vertex { position: block_pos + Vec3::from_array([1.0, 1.0, 1.0]), tex_coords: [0.0, 0.0] },
-            Vertex { position: block_pos + Vec3::from_array([1.0, -1.0, 1.0]), tex_coords: [0.0, 1.0] },
+            Vertex { position: block_pos + Vec3::from_array([1.0, -1.0, -1.0]), tex_coords: [1.0, 1.0], texture, normal, light, ambient_occlusion: 1.0 },
+            Vertex { position: block_pos + Vec3::from_array([1.0, 1.0, -1.0]), tex_coords: [1.0, 0.0], texture, normal, light, ambient_occlusion: 1.0 },
+            Vertex { position: block_pos + Vec3::from_array([1.0, 1.0, 1.0]), tex_coords: [0.0, 0.0], texture, normal, light, ambient_occlusion: 1.0 },
+            Vertex { position: block_pos + Vec3::from_array([1.0, -1.0, 1.0]), tex_coords: [0.0, 1.0], texture, normal, light, ambient_occlusion: 1.0 },
         ]
     }
 
-    fn as_verticecs_y_pos(&self, block_pos: Vec3) -> [Vertex; 4] {
+    fn as_verticecs_y_pos(&self, block_pos: Vec3, texture: u32, light: LightLevel) -> [Vertex; 4] {
+        let normal = Face::YPos.normal().as_vec3();
+        let light = Vertex::light_levels(light);
+
         [
-            Vertex { position: block_pos + Vec3::from_array([1.0, 1.0, -1.0]), tex_coords: [0.0, 0.0], }, // Top left
-            Vertex { position: block_pos + Vec3::from_array([-1.0, 1.0, -1.0]), tex_coords: [0.0, 1.0], }, // Bottom left 
-            Vertex { position: block_pos + Vec3::from_array([-1.0, 1.0, 1.0]), tex_coords: [1.0, 1.0], }, // Bottom right 
-            Vertex { position: block_pos + Vec3::from_array([1.0, 1.0, 1.0]), tex_coords: [1.0, 0.0], }, // Top right 
+            Vertex { position: block_pos + Vec3::from_array([1.0, 1.0, -1.0]), tex_coords: [0.0, 0.0], texture, normal, light, ambient_occlusion: 1.0 }, // Top left
+            Vertex { position: block_pos + Vec3::from_array([-1.0, 1.0, -1.0]), tex_coords: [0.0, 1.0], texture, normal, light, ambient_occlusion: 1.0 }, // Bottom left 
+            Vertex { position: block_pos + Vec3::from_array([-1.0, 1.0, 1.0]), tex_coords: [1.0, 1.0], texture, normal, light, ambient_occlusion: 1.0 }, // Bottom right 
+            Vertex { position: block_pos + Vec3::from_array([1.0, 1.0, 1.0]), tex_coords: [1.0, 0.0], texture, normal, light, ambient_occlusion: 1.0 }, // Top right 
         ] 
     }
 
-    fn as_vertices_y_neg(&self, block_pos: Vec3) -> [Vertex; 4] {
+    fn as_vertices_y_neg(&self, block_pos: Vec3, texture: u32, light: LightLevel) -> [Vertex; 4] {
+        let normal = Face::YNeg.normal().as_vec3();
+        let light = Vertex::light_levels(light);
+
         [
-            Vertex { position: block_pos + Vec3::from_array([1.0, -1.0, 1.0]), tex_coords: [1.0, 1.0] },
-            Vertex { position: block_pos + Vec3::from_array([-1.0, -1.0, 1.0]), tex_coords: [1.0, 0.0] },
-            Vertex { position: block_pos + Vec3::from_array([-1.0, -1.0, -1.0]), tex_coords: [0.0, 0.0] },
-            Vertex { position: block_pos + Vec3::from_array([1.0, -1.0, -1.0]), tex_coords: [0.0, 1.0] },
+            Vertex { position: block_pos + Vec3::from_array([1.0, -1.0, 1.0]), tex_coords: [1.0, 1.0], texture, normal, light, ambient_occlusion: 1.0 },
+            Vertex { position: block_pos + Vec3::from_array([-1.0, -1.0, 1.0]), tex_coords: [1.0, 0.0], texture, normal, light, ambient_occlusion: 1.0 },
+            Vertex { position: block_pos + Vec3::from_array([-1.0, -1.0, -1.0]), tex_coords: [0.0, 0.0], texture, normal, light, ambient_occlusion: 1.0 },
+            Vertex { position: block_pos + Vec3::from_array([1.0, -1.0, -1.0]), tex_coords: [0.0, 1.0], texture, normal, light, ambient_occlusion: 1.0 },
         ]
     }
 
-    const fn as_indices(block_num: u16) -> [u16; INDICES_PER_BLOCK] {
+    pub(crate) const fn as_indices(block_num: u32) -> [u32; 36] {
         let block_start_index = block_num * 24;
 
         [
@@ -138,201 +347,444 @@ impl Block {
         ]
     }
 
-    fn calc_rel_pos(block_num: u16) -> Vec3 {
-        let z = ((block_num as f32) / (CHUNK_SIZE_AXIS.pow(2) as f32)).ceil(); 
-        let y = (((block_num as f32) - z * CHUNK_SIZE_AXIS.pow(2) as f32) / CHUNK_SIZE_AXIS as f32).ceil();
-        let x = ((block_num as f32) - CHUNK_SIZE_AXIS as f32 * (y + CHUNK_SIZE_AXIS as f32 * z)).ceil();
+    pub(crate) fn as_vertices(&self, face: Face, block_pos: Vec3, light: LightLevel) -> [Vertex; 4] {
+        let texture = self.properties().texture_index(face);
+
+        match face {
+            Face::ZPos => self.as_vertices_z_pos(block_pos, texture, light),
+            Face::ZNeg => self.as_vertices_z_neg(block_pos, texture, light),
+            Face::XPos => self.as_vertices_right(block_pos, texture, light),
+            Face::XNeg => self.as_vertices_left(block_pos, texture, light),
+            Face::YPos => self.as_verticecs_y_pos(block_pos, texture, light),
+            Face::YNeg => self.as_vertices_y_neg(block_pos, texture, light),
+
+        }
 
-        Vec3::new(x, y, z) * Vec3::splat(2.0)
     }
 
-    fn get_neighbor_indexes(&self) -> BlockNeighbors {
-        let self_pos = Block::calc_rel_pos(self.block_num);
+}
 
-        let z_pos = self.block_num.checked_add(CHUNK_SIZE_AXIS.pow(2).try_into().unwrap());
-        let z_neg = self.block_num.checked_sub(CHUNK_SIZE_AXIS.pow(2).try_into().unwrap());
+/// The versions of a chunk's blocks and light that a mesh is built from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MeshVersion {
+    pub blocks: u64,
+    pub light: u64,
 
-        let mut y_pos = self.block_num.checked_add(CHUNK_SIZE_AXIS.try_into().unwrap());
-        let mut y_neg = self.block_num.checked_sub(CHUNK_SIZE_AXIS.try_into().unwrap());
+}
 
-        let y_pos_x = y_pos.map(|y_pos| Block::calc_rel_pos(y_pos).x);
-        let y_neg_x = y_neg.map(|y_neg| Block::calc_rel_pos(y_neg).x);
+// The length a chunk goes on a single axis
+pub const CHUNK_SIZE_AXIS: usize = 16;
+pub const CHUNK_SIZE: usize = CHUNK_SIZE_AXIS.pow(3);
 
-        if let Some(y_pos_x) = y_pos_x {
-            // Only count the y_pos neighbor if the x coord is the same as the current block
-            if y_pos_x != self_pos.x || Block::calc_rel_pos(y_pos.unwrap()).y - self_pos.y != 2.0 {
-                y_pos = None;
+/// A cube of blocks that is N blocks long on each axis
+pub struct Chunk<const N: usize = CHUNK_SIZE_AXIS> {
+    // The world position of the chunk's first block
+    chunk_pos: Vec3,
+    blocks: PalettedBlocks,
+    // The light level of every block, in index order. It's left empty while every block is dark
+    light: Vec<LightLevel>,
+    mesh: ChunkMesh,
+    // Goes up every time a block changes
+    block_version: u64,
+    // The block version that matches the chunk's saved copy, or what the generator makes, so it only needs saving once it's moved on
+    saved_version: Option<u64>,
+    // Goes up every time the light changes or the chunk is marked dirty, which changes how it looks without changing its blocks
+    light_version: u64,
+    // The version the mesh was built from, or None if it needs to be rebuilt regardless
+    meshed_version: Option<MeshVersion>,
+    // Goes up every time the mesh is rebuilt, so the renderer knows when to upload it again
+    mesh_generation: u64,
 
-            }
+}
+
+impl<const N: usize> Chunk<N> {
+    /// The number of blocks in the chunk
+    pub const SIZE: usize = N.pow(3);
+
+    /// Creates a chunk full of air at the given chunk coordinate, where each chunk coordinate is N blocks apart
+    pub fn new(chunk_coord: IVec3) -> Self {
+        Self::filled(chunk_coord, BlockId::AIR)
+
+    }
+
+    /// Creates a chunk where every block is the given block
+    pub fn filled(chunk_coord: IVec3, block: BlockId) -> Self {
+        let blocks = PalettedBlocks::filled(Self::SIZE, block);
+        let chunk_pos = chunk_coord.as_vec3() * Vec3::splat(N as f32 * BLOCK_SIZE);
+        
+        Self {
+            chunk_pos,
+            blocks,
+            light: Vec::new(),
+            mesh: ChunkMesh::default(),
+            block_version: 0,
+            saved_version: None,
+            light_version: 0,
+            meshed_version: None,
+            mesh_generation: 0,
 
         }
 
-        if let Some(y_neg_x) = y_neg_x {
-            if y_neg_x != self_pos.x || self_pos.y - Block::calc_rel_pos(y_neg.unwrap()).y != 2.0 {
-                y_neg = None;
+    }
+
+    /// Creates a chunk from its blocks, in index order
+    pub fn from_blocks(chunk_coord: IVec3, blocks: impl ExactSizeIterator<Item = BlockId>) -> Self {
+        assert_eq!(blocks.len(), Self::SIZE, "A chunk needs exactly {} blocks", Self::SIZE);
 
-           }
+        let mut chunk = Self::new(chunk_coord);
+        chunk.blocks = PalettedBlocks::from_blocks(blocks);
+
+        chunk
+
+    }
+
+    /// Copies the chunk's blocks without its light or mesh, so it can be meshed somewhere else
+    pub fn clone_blocks(&self) -> Self {
+        Self {
+            chunk_pos: self.chunk_pos,
+            blocks: self.blocks.clone(),
+            light: Vec::new(),
+            mesh: ChunkMesh::default(),
+            block_version: self.block_version,
+            saved_version: self.saved_version,
+            light_version: self.light_version,
+            meshed_version: None,
+            mesh_generation: 0,
+
+        }
+
+    }
+
+    /// Rebuilds the mesh if the chunk has changed since it was last built, returning whether it was rebuilt
+    pub fn update_graphics(&mut self, mesher: Mesher, light: &ChunkLight<N>, ambient_occlusion: bool) -> bool {
+        if !self.is_dirty() {
+            return false;
 
         }
 
-        let x_pos = self.block_num.checked_add(1);
-        let x_neg = self.block_num.checked_sub(1);
+        let mesh = mesher.mesh(self, light, ambient_occlusion);
+        self.set_mesh(mesh, self.mesh_version())
 
-        BlockNeighbors {
-            z_pos,
-            z_neg,
+    }
 
-            y_pos,
-            y_neg,
+    /// Replaces the mesh with one built from the given version.
+    /// If the blocks have changed since then the mesh is out of date, so it's thrown away and false is returned.
+    /// A mesh built from older light is still used until it's rebuilt, since only its shading is off
+    pub fn set_mesh(&mut self, mesh: ChunkMesh, version: MeshVersion) -> bool {
+        if version.blocks != self.block_version {
+            return false;
 
-            x_pos,
-            x_neg,
         }
 
+        self.mesh = mesh;
+        self.meshed_version = Some(version);
+        self.mesh_generation += 1;
+
+        true
+
     }
 
-}
+    /// Whether the blocks or light have changed since the mesh was last built
+    pub fn is_dirty(&self) -> bool {
+        self.meshed_version != Some(self.mesh_version())
 
-// The length a chunk goes on a single axis
-const CHUNK_SIZE_AXIS: usize = 3;
-pub const CHUNK_SIZE: usize = CHUNK_SIZE_AXIS.pow(3);
+    }
 
-pub struct Chunk {
-    chunk_pos: Vec3,
-    blocks: [Block; CHUNK_SIZE],
-    vertices: Vec<Vertex>,
-    indices: Vec<u16>,
+    /// Makes the mesh be rebuilt even though none of the chunk's blocks changed, such as when the blocks around it did
+    pub fn mark_dirty(&mut self) {
+        self.light_version += 1;
 
-}
+    }
 
-impl Chunk {
-    pub fn new(chunk_pos: Vec3) -> Self {
-        let mut blocks = [Block::new(0, true); CHUNK_SIZE];
+    /// Goes up every time one of the chunk's blocks changes, and nothing else
+    pub fn block_version(&self) -> u64 {
+        self.block_version
 
-        for block in blocks.iter_mut() {
-            if fastrand::u8(0..255) > 200 {
-                //block.solid = true;
-            }
+    }
+
+    /// Records that the chunk's blocks match its saved copy, or what the terrain generator makes, so it doesn't need saving until it's edited
+    pub fn mark_saved(&mut self) {
+        self.saved_version = Some(self.block_version);
+
+    }
+
+    /// Whether any blocks have changed since the chunk was last marked as saved
+    pub fn has_unsaved_edits(&self) -> bool {
+        self.saved_version != Some(self.block_version)
+
+    }
+
+    /// The version of the blocks and light a mesh built now would come from
+    pub fn mesh_version(&self) -> MeshVersion {
+        MeshVersion {
+            blocks: self.block_version,
+            light: self.light_version,
 
         }
-        
-        let mut old_self = Self {
-            chunk_pos,
-            blocks,
-            vertices: Vec::with_capacity(CHUNK_SIZE * VERTICES_PER_BLOCK),
-            indices: Vec::with_capacity(CHUNK_SIZE * INDICES_PER_BLOCK),
 
-        };
-        
-        old_self.update_block_nums();
-        old_self.update_vertices(Vec3::ZERO);
-        old_self.update_indices();
+    }
 
-        old_self
+    pub fn mesh_generation(&self) -> u64 {
+        self.mesh_generation
 
     }
 
-    pub fn update_graphics(&mut self, camera_pos: Vec3) {
-        self.sort_blocks(camera_pos);
-        self.update_vertices(camera_pos);
-        self.update_indices();
+    /// Every block in the chunk, in index order
+    pub fn blocks(&self) -> impl Iterator<Item = BlockId> + '_ {
+        self.blocks.iter()
 
     }
 
-    pub fn vertices(&self) -> &[Vertex] {
-        &self.vertices
+    /// The distinct blocks in the chunk. It can also have blocks that have been replaced since they were placed
+    pub fn palette(&self) -> &[BlockId] {
+        self.blocks.palette()
+
+    }
+
+    pub fn get_block(&self, local_pos: LocalPos) -> BlockId {
+        self.blocks.get(local_pos.to_index::<N>())
+
+    }
+
+    pub fn set_block(&mut self, local_pos: LocalPos, block: BlockId) {
+        let old_block = self.blocks.set(local_pos.to_index::<N>(), block);
+
+        if old_block != block {
+            self.block_version += 1;
+
+        }
+
+    }
+
+    pub fn get_light(&self, local_pos: LocalPos) -> LightLevel {
+        self.light.get(local_pos.to_index::<N>()).copied().unwrap_or_default()
+
+    }
+
+    /// Sets a block's light level, returning whether it changed. The mesh is rebuilt when it does, since faces are lit by it
+    pub fn set_light(&mut self, local_pos: LocalPos, level: LightLevel) -> bool {
+        if self.get_light(local_pos) == level {
+            return false;
+
+        }
+
+        if self.light.is_empty() {
+            self.light = vec![LightLevel::DARK; Self::SIZE];
+
+        }
+
+        self.light[local_pos.to_index::<N>()] = level;
+        self.mark_dirty();
+
+        true
 
     }
 
-    pub fn indices(&self) -> &[u16] {
-        &self.indices
+    /// The world position of the chunk's first block
+    pub fn world_pos(&self) -> Vec3 {
+        self.chunk_pos
 
     }
 
-    /// Sort the blocks by their distance to the player
-    fn sort_blocks(&mut self, camera_pos: Vec3) {
-        self.blocks.sort_unstable_by(|block1, block2| {
-            let pos1 = Block::calc_rel_pos(block1.block_num) + self.chunk_pos;
-            let pos2 = Block::calc_rel_pos(block2.block_num) + self.chunk_pos;
+    /// The space the chunk's blocks take up
+    pub fn aabb(&self) -> Aabb {
+        let min = self.chunk_pos - Vec3::splat(BLOCK_SIZE / 2.0);
 
-            let distance1 = pos1.distance_squared(camera_pos);
-            let distance2 = pos2.distance_squared(camera_pos);
+        Aabb::new(min, min + Vec3::splat(N as f32 * BLOCK_SIZE))
 
-            distance2.partial_cmp(&distance1).unwrap_or(Ordering::Equal)
+    }
+
+    pub fn mesh(&self) -> &ChunkMesh {
+        &self.mesh
+
+    }
 
-        });
+    /// Roughly how many bytes the chunk's blocks and mesh take up in memory
+    pub fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.blocks.memory_usage()
+            + self.light.capacity() * std::mem::size_of::<LightLevel>()
+            + self.mesh.vertices.capacity() * std::mem::size_of::<Vertex>()
+            + (self.mesh.indices.capacity() + self.mesh.translucent_indices.capacity()) * std::mem::size_of::<u32>()
 
     }
 
-    fn update_block_nums(&mut self) {
-        self.blocks.iter_mut().enumerate().for_each(|(block_num, block)| block.block_num = (block_num + 1).try_into().unwrap());
+    pub fn vertices(&self) -> &[Vertex] {
+        &self.mesh.vertices
 
     }
 
-    fn update_vertices(&mut self, camera_pos: Vec3) {
-        self.vertices.clear();
+    pub fn indices(&self) -> &[u32] {
+        &self.mesh.indices
 
-        for block in self.blocks.iter() {
-            let mut current_block_vertices = [Vertex::zero(); VERTICES_PER_BLOCK];
+    }
 
-            if block.solid {
-                // The block's position relative to the chunk center
-                let block_rel_pos = Block::calc_rel_pos(block.block_num); 
-                let block_world_pos = self.chunk_pos + block_rel_pos;
+}
 
-                let block_neighbors = block.get_neighbor_indexes();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                let draw_face = |block_face_vertices: &mut [Vertex; 4], neighbor_block_index: Option<u16>, calced_block_vertices: [Vertex; 4]| {
-                    block_face_vertices.copy_from_slice(&
-                    match neighbor_block_index {
-                        Some(neighbor_block_index) => {
-                            let neighbor_block_index: usize = neighbor_block_index.into();
-                            let neighbor_block = self.blocks.get(neighbor_block_index);
+    fn assert_index_round_trips<const N: usize>() {
+        for index in 0..N.pow(3) {
+            let pos = LocalPos::from_index::<N>(index);
 
-                            match neighbor_block {
-                                Some(neighbor_block) => {
-                                    match neighbor_block.solid {
-                                        true => [Vertex::zero(); 4],
-                                        false => calced_block_vertices,
+            assert!(pos.is_in_chunk::<N>(), "{pos:?} is outside of a chunk of size {N}");
+            assert_eq!(pos.to_index::<N>(), index, "{pos:?} in a chunk of size {N}");
 
-                                    }
-                                },
-                                None => calced_block_vertices,
-                            }
-                        },
-                        None => calced_block_vertices,
+        }
 
-                    });
-                };
+    }
+
+    fn assert_neighbors_stop_at_edges<const N: usize>() {
+        for pos in LocalPos::all::<N>() {
+            for face in Face::ALL {
+                let expected = pos.as_ivec3() + face.normal();
+                let on_edge = expected.cmplt(IVec3::ZERO).any() || expected.cmpge(IVec3::splat(N as i32)).any();
 
-                current_block_vertices[8..12].copy_from_slice(&block.as_vertices_right(block_world_pos));
-                current_block_vertices[12..16].copy_from_slice(&block.as_vertices_left(block_world_pos));
-                //draw_face((&mut current_block_vertices[8..12]).try_into().unwrap(), block_neighbors.x_neg, block.as_vertices_right(block_world_pos));
-                //draw_face((&mut current_block_vertices[12..16]).try_into().unwrap(), block_neighbors.x_pos, block.as_vertices_left(block_world_pos));
+                match pos.neighbor::<N>(face) {
+                    // A neighbor that wrapped onto another row would be in the chunk but not next to pos
+                    Some(neighbor) => {
+                        assert!(!on_edge, "{pos:?} has a {face:?} neighbor outside of a chunk of size {N}");
+                        assert_eq!(neighbor.as_ivec3(), expected, "{pos:?} {face:?} in a chunk of size {N}");
+                        assert_eq!(neighbor.to_index::<N>() as i32 - pos.to_index::<N>() as i32, index_offset::<N>(face));
 
-                draw_face((&mut current_block_vertices[16..20]).try_into().unwrap(), block_neighbors.y_pos, block.as_verticecs_y_pos(block_world_pos));
-                draw_face((&mut current_block_vertices[20..24]).try_into().unwrap(), block_neighbors.y_neg, block.as_vertices_y_neg(block_world_pos));
+                    },
+                    None => assert!(on_edge, "{pos:?} is missing its {face:?} neighbor in a chunk of size {N}"),
 
-                draw_face((&mut current_block_vertices[4..8]).try_into().unwrap(), block_neighbors.z_neg, block.as_vertices_z_neg(block_world_pos)); 
-                draw_face((&mut current_block_vertices[0..4]).try_into().unwrap(), block_neighbors.z_pos, block.as_vertices_z_pos(block_world_pos)); 
+                }
 
             }
 
-            self.vertices.extend_from_slice(&current_block_vertices);
+        }
+
+    }
+
+    // How far apart two neighboring positions are in index order
+    fn index_offset<const N: usize>(face: Face) -> i32 {
+        let normal = face.normal();
+
+        normal.x + N as i32 * (normal.y + N as i32 * normal.z)
+
+    }
+
+    // Checks neighbors against every pair of positions in the chunk, rather than trusting the index math
+    fn assert_neighbors_are_adjacent<const N: usize>() {
+        for pos in LocalPos::all::<N>() {
+            let mut neighbors: Vec<(Face, LocalPos)> = pos.neighbors::<N>().collect();
+            let mut expected: Vec<(Face, LocalPos)> = LocalPos::all::<N>()
+                .filter_map(|other| {
+                    let offset = other.as_ivec3() - pos.as_ivec3();
+                    Face::ALL.into_iter().find(|face| face.normal() == offset).map(|face| (face, other))
+
+                })
+                .collect();
+
+            neighbors.sort_by_key(|(_, neighbor)| neighbor.to_index::<N>());
+            expected.sort_by_key(|(_, neighbor)| neighbor.to_index::<N>());
+
+            assert_eq!(neighbors, expected, "{pos:?} in a chunk of size {N}");
 
         }
 
     }
 
-    fn update_indices(&mut self) {
-        self.indices.clear();
+    #[test]
+    fn index_round_trips_for_every_size() {
+        assert_index_round_trips::<1>();
+        assert_index_round_trips::<2>();
+        assert_index_round_trips::<3>();
+        assert_index_round_trips::<16>();
+
+    }
+
+    #[test]
+    fn neighbors_stop_at_chunk_edges_for_every_size() {
+        assert_neighbors_stop_at_edges::<1>();
+        assert_neighbors_stop_at_edges::<2>();
+        assert_neighbors_stop_at_edges::<3>();
+        assert_neighbors_stop_at_edges::<16>();
+
+    }
+
+    #[test]
+    fn neighbors_are_every_adjacent_position() {
+        assert_neighbors_are_adjacent::<1>();
+        assert_neighbors_are_adjacent::<2>();
+        assert_neighbors_are_adjacent::<3>();
+        assert_neighbors_are_adjacent::<5>();
+
+    }
+
+    #[test]
+    fn all_visits_every_position_in_index_order() {
+        let positions: Vec<LocalPos> = LocalPos::all::<3>().collect();
 
-        for i in 0..self.blocks.len() {  
-            let current_block_indices = Block::as_indices(i.try_into().unwrap());
-            self.indices.extend_from_slice(&current_block_indices);
+        assert_eq!(positions.len(), 27);
+        assert_eq!(positions[0], LocalPos::new(0, 0, 0));
+        assert_eq!(positions[1], LocalPos::new(1, 0, 0));
+        assert_eq!(positions[3], LocalPos::new(0, 1, 0));
+        assert_eq!(positions[9], LocalPos::new(0, 0, 1));
+        assert_eq!(positions[26], LocalPos::new(2, 2, 2));
+
+        for (index, pos) in positions.into_iter().enumerate() {
+            assert_eq!(pos.to_index::<3>(), index);
 
         }
 
     }
 
+    #[test]
+    fn positions_past_the_last_block_are_outside_of_the_chunk() {
+        assert!(LocalPos::new(15, 15, 15).is_in_chunk::<16>());
+        assert!(!LocalPos::new(16, 0, 0).is_in_chunk::<16>());
+        assert!(!LocalPos::new(0, 16, 0).is_in_chunk::<16>());
+        assert!(!LocalPos::new(0, 0, 16).is_in_chunk::<16>());
+        assert!(!LocalPos::new(0, 0, 0).is_in_chunk::<0>());
+
+    }
+
+    #[test]
+    fn light_changes_are_not_block_edits() {
+        let mut chunk: Chunk = Chunk::new(IVec3::ZERO);
+        let block_version = chunk.block_version();
+
+        chunk.set_light(LocalPos::new(1, 2, 3), LightLevel::new(MAX_LIGHT, 0));
+        chunk.mark_dirty();
+
+        assert_eq!(chunk.block_version(), block_version);
+        assert!(chunk.is_dirty());
+
+    }
+
+    #[test]
+    fn meshes_from_older_light_are_kept_but_rebuilt() {
+        let mut chunk: Chunk = Chunk::new(IVec3::ZERO);
+        let version = chunk.mesh_version();
+
+        // The light changes while the mesh is being built
+        chunk.set_light(LocalPos::new(1, 2, 3), LightLevel::new(MAX_LIGHT, 0));
+
+        assert!(chunk.set_mesh(ChunkMesh::default(), version));
+        assert_eq!(chunk.mesh_generation(), 1);
+        assert!(chunk.is_dirty());
+
+        assert!(chunk.set_mesh(ChunkMesh::default(), chunk.mesh_version()));
+        assert!(!chunk.is_dirty());
+
+    }
+
+    #[test]
+    fn meshes_from_older_blocks_are_thrown_away() {
+        let mut chunk: Chunk = Chunk::new(IVec3::ZERO);
+        let version = chunk.mesh_version();
+
+        chunk.set_block(LocalPos::new(1, 2, 3), BlockRegistry::global().id("stone").unwrap());
+
+        assert!(!chunk.set_mesh(ChunkMesh::default(), version));
+        assert_eq!(chunk.mesh_generation(), 0);
+        assert!(chunk.is_dirty());
+
+    }
+
 }
@@ -1,9 +1,20 @@
+use std::f32::consts::FRAC_PI_2;
+
 use glam::{Vec3, Vec3A, Mat4};
 use wgpu::SurfaceConfiguration;
 
+use crate::frustum::Frustum;
+
+// How close the pitch can get to looking straight up or down, since the view matrix breaks when looking along the up axis
+const PITCH_LIMIT: f32 = FRAC_PI_2 - 0.001;
+
+/// A first person camera, which looks in the direction given by its yaw and pitch
 pub struct Camera {
     pos: Vec3A,
-    target: Vec3A,
+    // The rotation around the y axis in radians, where 0 looks along +x
+    yaw: f32,
+    // The rotation up or down in radians, where 0 looks at the horizon
+    pitch: f32,
     up_axis: Vec3A,
     aspect: f32,
     fovy: f32,
@@ -15,7 +26,7 @@ pub struct Camera {
 impl Camera {
     pub fn build_view_projection_matrix(&self) -> Mat4 {
         // Moves world to be at pos and rot of cam
-        let view = Mat4::look_at_rh(self.pos.into(), self.target.into(), self.up_axis.into());
+        let view = Mat4::look_at_rh(self.pos.into(), (self.pos + self.forward()).into(), self.up_axis.into());
         // Adds depth by transforming vertices in a way that makes them smamller or larger
         // depending on distance
         let proj  = Mat4::perspective_rh(self.fovy, self.aspect, self.znear, self.zfar);
@@ -23,76 +34,75 @@ impl Camera {
         proj * view
 
     }
+
+    /// The space the camera can see
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_view_projection(self.build_view_projection_matrix())
+
+    }
     
     pub fn new() -> Self {
         Self {
-            pos: (800.0, 801.0, 52.0).into(),
-            target: (0.0, 0.0, 0.0).into(),
+            pos: (40.0, 80.0, 40.0).into(),
+            // Look back towards the origin
+            yaw: -3.0 * std::f32::consts::FRAC_PI_4,
+            pitch: -0.35,
             up_axis: Vec3A::Y,
             aspect: 1.0,
-            fovy: 45.0,
+            fovy: 45.0_f32.to_radians(),
             znear: 0.1,
-            zfar: 100.0,
+            zfar: 200.0,
         }
 
     }
 
-    pub fn update_aspect(&mut self, config: &SurfaceConfiguration) {
-        self.aspect = config.width as f32 / config.height as f32;
+    pub fn pos(&self) -> Vec3 {
+        self.pos.into()
 
     }
 
-    pub fn move_forward(&mut self, speed: f32) {
-        let forward = self.target - self.pos;
-        let forward_norm = forward.normalize_or_zero();
-        // Equivalent of cgmath magnitude
-
-        self.pos += forward_norm * speed;
+    /// Sets how far away things can be seen, in world units
+    pub fn set_view_distance(&mut self, distance: f32) {
+        self.zfar = distance;
 
     }
 
-    pub fn move_backward(&mut self, speed: f32) {
-        let forward = self.target - self.pos;
-        let forward_norm = forward.normalize();
-        // Equivalent of cgmath magnitude
-
-        self.pos -= forward_norm * speed;
+    pub fn update_aspect(&mut self, config: &SurfaceConfiguration) {
+        self.aspect = config.width as f32 / config.height as f32;
 
     }
 
-    pub fn move_right(&mut self, speed: f32) {
-        let forward = self.target - self.pos;
-        let forward_norm = forward.normalize_or_zero();
-        let forward_mag = forward.dot(forward).sqrt(); 
+    /// The unit vector the camera is looking along
+    pub fn forward(&self) -> Vec3A {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
 
-        let right = forward_norm.cross(self.up_axis);
+        Vec3A::new(cos_pitch * cos_yaw, sin_pitch, cos_pitch * sin_yaw)
 
-        //self.pos = self.target - (forward - right * speed).normalize() * forward_mag;
-        self.pos.z -= speed;
     }
 
-    pub fn move_left(&mut self, speed: f32) {
-        let forward = self.target - self.pos;
-        let forward_norm = forward.normalize();
-        let forward_mag = forward.dot(forward).sqrt(); 
+    /// The unit vector the camera is looking along, ignoring the pitch, which is the direction walking forward moves in
+    pub fn forward_horizontal(&self) -> Vec3A {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        Vec3A::new(cos_yaw, 0.0, sin_yaw)
 
-        let right = forward_norm.cross(self.up_axis);
-
-        self.pos = self.target - (forward + right * speed).normalize() * forward_mag;
     }
-    
-    pub fn move_up(&mut self, speed: f32) {
-        self.pos.y -= speed;
+
+    /// The unit vector pointing to the right of the camera, parallel to the ground
+    pub fn right(&self) -> Vec3A {
+        self.forward_horizontal().cross(self.up_axis)
 
     }
 
-    pub fn move_down(&mut self, speed: f32) {
-        self.pos.y += speed;
+    /// Turns the camera by the given angles in radians, where positive yaw turns right and positive pitch looks up
+    pub fn rotate(&mut self, yaw_delta: f32, pitch_delta: f32) {
+        self.yaw = (self.yaw + yaw_delta).rem_euclid(std::f32::consts::TAU);
+        self.pitch = (self.pitch + pitch_delta).clamp(-PITCH_LIMIT, PITCH_LIMIT);
 
     }
-    
-    pub fn pos(&self) -> Vec3 {
-        self.pos.into()
+
+    pub fn set_pos(&mut self, pos: Vec3) {
+        self.pos = pos.into();
 
     }
 
@@ -125,3 +135,108 @@ impl CameraUniform {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{FRAC_PI_2, PI};
+
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn camera_with_yaw(yaw: f32) -> Camera {
+        let mut camera = Camera::new();
+        camera.yaw = yaw;
+        camera.pitch = 0.0;
+
+        camera
+
+    }
+
+    #[test]
+    fn right_is_perpendicular_to_forward() {
+        for step in 0..16 {
+            let mut camera = camera_with_yaw(step as f32 * PI / 8.0);
+            camera.pitch = 0.6;
+
+            assert!((camera.forward().length() - 1.0).abs() < EPSILON);
+            assert!((camera.forward_horizontal().length() - 1.0).abs() < EPSILON);
+            assert!((camera.right().length() - 1.0).abs() < EPSILON);
+            assert!(camera.right().dot(camera.forward_horizontal()).abs() < EPSILON);
+            assert!(camera.right().y.abs() < EPSILON);
+            assert!(camera.forward_horizontal().y.abs() < EPSILON);
+
+        }
+
+    }
+
+    #[test]
+    fn strafing_follows_the_yaw() {
+        let directions = [
+            (0.0, Vec3A::X, Vec3A::Z),
+            (FRAC_PI_2, Vec3A::Z, Vec3A::NEG_X),
+            (PI, Vec3A::NEG_X, Vec3A::NEG_Z),
+            (3.0 * FRAC_PI_2, Vec3A::NEG_Z, Vec3A::X),
+        ];
+
+        for (yaw, forward, right) in directions {
+            let camera = camera_with_yaw(yaw);
+
+            assert!(camera.forward_horizontal().abs_diff_eq(forward, EPSILON), "forward at yaw {yaw}");
+            assert!(camera.right().abs_diff_eq(right, EPSILON), "right at yaw {yaw}");
+
+        }
+
+    }
+
+    #[test]
+    fn right_is_on_the_right_of_the_screen() {
+        for step in 0..8 {
+            let camera = camera_with_yaw(step as f32 * PI / 4.0);
+            let target = camera.pos + camera.forward() * 10.0 + camera.right();
+            let clip = camera.build_view_projection_matrix() * Vec3::from(target).extend(1.0);
+
+            assert!(clip.x / clip.w > 0.0);
+            assert!((clip.y / clip.w).abs() < EPSILON);
+
+        }
+
+    }
+
+    #[test]
+    fn positive_yaw_turns_right() {
+        let mut camera = camera_with_yaw(0.3);
+        let right = camera.right();
+        camera.rotate(0.1, 0.0);
+
+        assert!(camera.forward_horizontal().dot(right) > 0.0);
+
+    }
+
+    #[test]
+    fn rotate_clamps_the_pitch() {
+        let mut camera = camera_with_yaw(0.0);
+
+        camera.rotate(0.0, 10.0);
+        assert_eq!(camera.pitch, PITCH_LIMIT);
+        assert!(camera.forward().y < 1.0);
+
+        camera.rotate(0.0, -0.5);
+        assert_eq!(camera.pitch, PITCH_LIMIT - 0.5);
+
+        camera.rotate(0.0, -10.0);
+        assert_eq!(camera.pitch, -PITCH_LIMIT);
+        assert!(camera.forward().y > -1.0);
+
+    }
+
+    #[test]
+    fn yaw_wraps_around() {
+        let mut camera = camera_with_yaw(0.0);
+        camera.rotate(-0.5, 0.0);
+
+        assert!((camera.yaw - (std::f32::consts::TAU - 0.5)).abs() < EPSILON);
+
+    }
+
+}
@@ -0,0 +1,147 @@
+use instant::{Duration, Instant};
+
+// The most simulation steps run in a single frame, so a long stall (like dragging the window) doesn't
+// make the game spend every following frame catching up
+const MAX_STEPS_PER_FRAME: u32 = 8;
+
+/// Measures how long each frame takes and splits that time into fixed length simulation steps,
+/// so the simulation runs at the same speed no matter the frame rate
+pub struct GameClock {
+    last_frame: Option<Instant>,
+    frame_delta: Duration,
+    // Time that has passed but hasn't been simulated yet
+    accumulator: Duration,
+    timestep: Duration,
+
+}
+
+impl GameClock {
+    pub fn new(ticks_per_second: u32) -> Self {
+        Self {
+            last_frame: None,
+            frame_delta: Duration::ZERO,
+            accumulator: Duration::ZERO,
+            timestep: Duration::from_secs(1) / ticks_per_second.max(1),
+
+        }
+
+    }
+
+    /// Measures the time since the last frame, returning how many simulation steps should be run this frame
+    pub fn tick(&mut self) -> u32 {
+        let now = Instant::now();
+        let elapsed = self.last_frame.map(|last_frame| now - last_frame).unwrap_or(Duration::ZERO);
+        self.last_frame = Some(now);
+
+        self.advance(elapsed)
+
+    }
+
+    /// Adds elapsed time to the clock, returning how many simulation steps it's now behind by
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        self.frame_delta = elapsed;
+        self.accumulator += elapsed;
+
+        let mut steps = 0;
+
+        while self.accumulator >= self.timestep {
+            self.accumulator -= self.timestep;
+            steps += 1;
+
+        }
+
+        if steps > MAX_STEPS_PER_FRAME {
+            log::warn!("The simulation is {steps} steps behind, skipping {} of them", steps - MAX_STEPS_PER_FRAME);
+            steps = MAX_STEPS_PER_FRAME;
+
+        }
+
+        steps
+
+    }
+
+    /// The length of a single simulation step in seconds
+    pub fn timestep(&self) -> f32 {
+        self.timestep.as_secs_f32()
+
+    }
+
+    /// How long the last frame took in seconds
+    pub fn frame_delta(&self) -> f32 {
+        self.frame_delta.as_secs_f32()
+
+    }
+
+    /// How far between the last simulation step and the next one the current frame is, from 0 to 1
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.timestep.as_secs_f32()
+
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Ten ticks a second, so every step is exactly 100 milliseconds
+    fn clock() -> GameClock {
+        GameClock::new(10)
+
+    }
+
+    fn assert_alpha(clock: &GameClock, alpha: f32) {
+        assert!((clock.alpha() - alpha).abs() < 1e-4, "alpha is {}, not {alpha}", clock.alpha());
+
+    }
+
+    #[test]
+    fn whole_steps_are_run_and_the_rest_is_carried_over() {
+        let mut clock = clock();
+
+        assert_eq!(clock.advance(Duration::from_millis(250)), 2);
+        assert_alpha(&clock, 0.5);
+        assert!((clock.frame_delta() - 0.25).abs() < 1e-6);
+
+        assert_eq!(clock.advance(Duration::from_millis(50)), 1);
+        assert_alpha(&clock, 0.0);
+
+    }
+
+    #[test]
+    fn short_frames_add_up_to_a_step() {
+        let mut clock = clock();
+
+        assert_eq!(clock.advance(Duration::from_millis(40)), 0);
+        assert_alpha(&clock, 0.4);
+        assert_eq!(clock.advance(Duration::from_millis(40)), 0);
+        assert_alpha(&clock, 0.8);
+        assert_eq!(clock.advance(Duration::from_millis(40)), 1);
+        assert_alpha(&clock, 0.2);
+
+    }
+
+    #[test]
+    fn long_stalls_are_clamped_and_the_skipped_steps_are_dropped() {
+        let mut clock = clock();
+
+        assert_eq!(clock.advance(Duration::from_millis(5030)), MAX_STEPS_PER_FRAME);
+        assert_alpha(&clock, 0.3);
+
+        // The steps that were skipped aren't made up for in the following frames
+        assert_eq!(clock.advance(Duration::ZERO), 0);
+        assert_eq!(clock.advance(Duration::from_millis(70)), 1);
+        assert_alpha(&clock, 0.0);
+
+    }
+
+    #[test]
+    fn exactly_the_max_steps_are_not_clamped() {
+        let mut clock = clock();
+
+        assert_eq!(clock.advance(Duration::from_millis(100) * MAX_STEPS_PER_FRAME), MAX_STEPS_PER_FRAME);
+        assert_alpha(&clock, 0.0);
+
+    }
+
+}
@@ -0,0 +1,182 @@
+use glam::{Mat4, Vec3, Vec4};
+
+use crate::physics::Aabb;
+
+/// The space a camera can see, bounded by six planes that face inwards
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    // Each plane is a normal in xyz and a distance in w, where a point p is on the inside when normal.dot(p) + distance >= 0.
+    // In order they're the left, right, bottom, top, near and far planes
+    planes: [Vec4; 6],
+
+}
+
+impl Frustum {
+    /// Extracts the frustum from a view projection matrix that maps it onto wgpu's clip space, where depth goes from 0 to 1
+    pub fn from_view_projection(view_proj: Mat4) -> Self {
+        let rows = [0, 1, 2, 3].map(|row| view_proj.row(row));
+
+        let planes = [
+            rows[3] + rows[0],
+            rows[3] - rows[0],
+            rows[3] + rows[1],
+            rows[3] - rows[1],
+            // Depth starts at 0 rather than -1 like OpenGL, so the near plane is just the depth row
+            rows[2],
+            rows[3] - rows[2],
+        ];
+
+        // Normalizing the planes makes their w the actual distance from the origin
+        Self {
+            planes: planes.map(|plane| plane / plane.truncate().length()),
+
+        }
+
+    }
+
+    pub fn planes(&self) -> &[Vec4; 6] {
+        &self.planes
+
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        self.planes.iter().all(|plane| plane.truncate().dot(point) + plane.w >= 0.0)
+
+    }
+
+    /// Whether any part of a box could be inside the frustum. Boxes just outside of the frustum's corners can still count as inside,
+    /// which is fine for culling since they're only drawn when they didn't need to be
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // If even the corner of the box furthest along the plane's normal is outside, the whole box is
+            let normal = plane.truncate();
+            let corner = Vec3::select(normal.cmpge(Vec3::ZERO), aabb.max, aabb.min);
+
+            normal.dot(corner) + plane.w >= 0.0
+
+        })
+
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ZNEAR: f32 = 1.0;
+    const ZFAR: f32 = 100.0;
+    // How far in front of the camera the boxes are, where a 90 degree field of view is this wide on each side
+    const DEPTH: f32 = 10.0;
+
+    // A camera at the origin looking down -z, with a square 90 degree field of view so the side planes are at 45 degrees
+    fn frustum() -> Frustum {
+        let view = Mat4::look_at_rh(Vec3::ZERO, Vec3::NEG_Z, Vec3::Y);
+        let proj = Mat4::perspective_rh(90.0_f32.to_radians(), 1.0, ZNEAR, ZFAR);
+
+        Frustum::from_view_projection(proj * view)
+
+    }
+
+    fn cube(center: Vec3, half_size: f32) -> Aabb {
+        Aabb::new(center - Vec3::splat(half_size), center + Vec3::splat(half_size))
+
+    }
+
+    // The four directions to the side planes, from the middle of the view
+    const SIDES: [Vec3; 4] = [Vec3::NEG_X, Vec3::X, Vec3::NEG_Y, Vec3::Y];
+
+    #[test]
+    fn boxes_in_front_are_visible() {
+        let frustum = frustum();
+
+        assert!(frustum.intersects_aabb(&cube(Vec3::NEG_Z * DEPTH, 1.0)));
+        assert!(frustum.contains_point(Vec3::NEG_Z * DEPTH));
+
+        // Close to the sides, but still inside
+        for side in SIDES {
+            assert!(frustum.intersects_aabb(&cube(Vec3::NEG_Z * DEPTH + side * (DEPTH - 1.0), 0.25)), "{side}");
+
+        }
+
+    }
+
+    #[test]
+    fn boxes_behind_are_not_visible() {
+        let frustum = frustum();
+
+        assert!(!frustum.intersects_aabb(&cube(Vec3::Z * DEPTH, 1.0)));
+        assert!(!frustum.contains_point(Vec3::Z * DEPTH));
+
+    }
+
+    #[test]
+    fn boxes_beyond_the_far_plane_are_not_visible() {
+        let frustum = frustum();
+
+        assert!(!frustum.intersects_aabb(&cube(Vec3::NEG_Z * (ZFAR + 2.0), 1.0)));
+        assert!(frustum.intersects_aabb(&cube(Vec3::NEG_Z * (ZFAR - 2.0), 1.0)));
+
+    }
+
+    #[test]
+    fn boxes_just_outside_the_sides_are_not_visible() {
+        let frustum = frustum();
+
+        for side in SIDES {
+            // The side planes are DEPTH away from the middle of the view at this depth, so this is a block past them
+            let center = Vec3::NEG_Z * DEPTH + side * (DEPTH + 1.0);
+
+            assert!(!frustum.intersects_aabb(&cube(center, 0.25)), "{side}");
+            assert!(!frustum.contains_point(center), "{side}");
+
+        }
+
+    }
+
+    #[test]
+    fn boxes_straddling_a_plane_are_visible() {
+        let frustum = frustum();
+
+        for side in SIDES {
+            let center = Vec3::NEG_Z * DEPTH + side * DEPTH;
+            assert!(frustum.intersects_aabb(&cube(center, 0.25)), "{side}");
+
+        }
+
+        assert!(frustum.intersects_aabb(&cube(Vec3::NEG_Z * ZFAR, 1.0)));
+        assert!(frustum.intersects_aabb(&cube(Vec3::NEG_Z * ZNEAR, 0.25)));
+
+    }
+
+    #[test]
+    fn the_near_plane_is_at_depth_zero() {
+        let frustum = frustum();
+        let near = frustum.planes()[4];
+
+        // wgpu's depth goes from 0 at the near plane, so it's exactly ZNEAR in front of the camera
+        assert!(near.truncate().abs_diff_eq(Vec3::NEG_Z, 1e-5), "{near}");
+        assert!((near.w + ZNEAR).abs() < 1e-4, "{near}");
+
+        assert!(frustum.contains_point(Vec3::NEG_Z * (ZNEAR + 0.01)));
+        // With OpenGL's depth from -1 the near plane would end up around half as far away, and this would be inside
+        assert!(!frustum.contains_point(Vec3::NEG_Z * ZNEAR * 0.75));
+        assert!(!frustum.intersects_aabb(&cube(Vec3::NEG_Z * ZNEAR * 0.5, 0.1)));
+
+    }
+
+    #[test]
+    fn follows_the_camera_around() {
+        let eye = Vec3::new(50.0, 20.0, -30.0);
+        let view = Mat4::look_at_rh(eye, eye + Vec3::X, Vec3::Y);
+        let proj = Mat4::perspective_rh(90.0_f32.to_radians(), 1.0, ZNEAR, ZFAR);
+        let frustum = Frustum::from_view_projection(proj * view);
+
+        assert!(frustum.intersects_aabb(&cube(eye + Vec3::X * DEPTH, 1.0)));
+        assert!(!frustum.intersects_aabb(&cube(eye - Vec3::X * DEPTH, 1.0)));
+        // Where the camera at the origin would have seen it
+        assert!(!frustum.intersects_aabb(&cube(Vec3::NEG_Z * DEPTH, 1.0)));
+
+    }
+
+}
@@ -0,0 +1,218 @@
+use std::collections::HashSet;
+#[cfg(not(target_arch="wasm32"))]
+use std::sync::{mpsc, Arc, Mutex};
+#[cfg(target_arch="wasm32")]
+use std::collections::VecDeque;
+
+use glam::IVec3;
+
+use crate::block::{Chunk, MeshVersion};
+use crate::lighting::{self, ChunkLight, SkyHeights};
+use crate::mesh::{ChunkMesh, Mesher};
+
+// wasm32 can't spawn threads, so this many jobs are run on the main thread each time the job system is polled instead
+#[cfg(target_arch="wasm32")]
+const INLINE_JOBS_PER_POLL: usize = 4;
+
+type Job = Box<dyn FnOnce() -> JobResult + Send>;
+
+/// The output of a finished job
+pub enum JobResult {
+    Generated {
+        chunk_coord: IVec3,
+        // Already lit by itself with these sky heights
+        chunk: Chunk,
+        sky_heights: SkyHeights,
+
+    },
+    Meshed {
+        chunk_coord: IVec3,
+        // The version of the chunk the mesh was built from
+        version: MeshVersion,
+        mesh: ChunkMesh,
+
+    },
+
+}
+
+/// Generates and meshes chunks away from the main thread, on worker threads where possible
+pub struct JobSystem {
+    #[cfg(not(target_arch="wasm32"))]
+    job_sender: mpsc::Sender<Job>,
+    #[cfg(not(target_arch="wasm32"))]
+    result_receiver: mpsc::Receiver<JobResult>,
+    #[cfg(target_arch="wasm32")]
+    queue: VecDeque<Job>,
+    // Chunks that have a job in flight, so the same work isn't queued twice
+    generating: HashSet<IVec3>,
+    meshing: HashSet<IVec3>,
+
+}
+
+impl JobSystem {
+    /// Creates a job system with one worker per spare CPU core
+    #[cfg(not(target_arch="wasm32"))]
+    pub fn new() -> Self {
+        let workers = std::thread::available_parallelism()
+            .map(|cores| cores.get().saturating_sub(1).max(1))
+            .unwrap_or(1);
+
+        Self::with_workers(workers)
+
+    }
+
+    #[cfg(not(target_arch="wasm32"))]
+    pub fn with_workers(workers: usize) -> Self {
+        let (job_sender, job_receiver) = mpsc::channel::<Job>();
+        let (result_sender, result_receiver) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        for i in 0..workers.max(1) {
+            let job_receiver = Arc::clone(&job_receiver);
+            let result_sender = result_sender.clone();
+
+            std::thread::Builder::new()
+                .name(format!("chunk worker {i}"))
+                .spawn(move || loop {
+                    // The lock is only held while waiting for a job, not while running it
+                    let job = match job_receiver.lock().unwrap().recv() {
+                        Ok(job) => job,
+                        // The job system has been dropped
+                        Err(_) => break,
+
+                    };
+
+                    if result_sender.send(job()).is_err() {
+                        break;
+
+                    }
+
+                })
+                .expect("Failed to spawn chunk worker thread");
+
+        }
+
+        Self {
+            job_sender,
+            result_receiver,
+            generating: HashSet::new(),
+            meshing: HashSet::new(),
+
+        }
+
+    }
+
+    #[cfg(target_arch="wasm32")]
+    pub fn new() -> Self {
+        Self {
+            queue: VecDeque::new(),
+            generating: HashSet::new(),
+            meshing: HashSet::new(),
+
+        }
+
+    }
+
+    fn submit(&mut self, job: Job) {
+        #[cfg(not(target_arch="wasm32"))]
+        self.job_sender.send(job).expect("Chunk worker threads have stopped");
+
+        #[cfg(target_arch="wasm32")]
+        self.queue.push_back(job);
+
+    }
+
+    /// Queues a chunk to be generated by the given function, unless it's already being generated.
+    /// The function also gives the sky heights of the chunk's columns, which it's lit with before it's sent back
+    pub fn generate<F>(&mut self, chunk_coord: IVec3, generate: F)
+    where F: FnOnce(IVec3) -> (Chunk, SkyHeights) + Send + 'static {
+        if !self.generating.insert(chunk_coord) {
+            return;
+
+        }
+
+        self.submit(Box::new(move || {
+            let (mut chunk, sky_heights) = generate(chunk_coord);
+            lighting::light_chunk(chunk_coord, &mut chunk, &sky_heights);
+
+            JobResult::Generated { chunk_coord, chunk, sky_heights }
+
+        }));
+
+    }
+
+    /// Queues a chunk to be meshed with the given light, unless it's already being meshed.
+    /// Only the chunk's blocks are copied, so it can keep being edited in the meantime
+    pub fn mesh(&mut self, chunk_coord: IVec3, chunk: &Chunk, light: ChunkLight, mesher: Mesher, ambient_occlusion: bool) {
+        if !self.meshing.insert(chunk_coord) {
+            return;
+
+        }
+
+        let chunk = chunk.clone_blocks();
+
+        self.submit(Box::new(move || JobResult::Meshed {
+            chunk_coord,
+            version: chunk.mesh_version(),
+            mesh: mesher.mesh(&chunk, &light, ambient_occlusion),
+
+        }));
+
+    }
+
+    pub fn is_generating(&self, chunk_coord: IVec3) -> bool {
+        self.generating.contains(&chunk_coord)
+
+    }
+
+    pub fn is_meshing(&self, chunk_coord: IVec3) -> bool {
+        self.meshing.contains(&chunk_coord)
+
+    }
+
+    /// The number of chunks that are being generated
+    pub fn generating_count(&self) -> usize {
+        self.generating.len()
+
+    }
+
+    /// The number of jobs that haven't finished yet
+    pub fn pending(&self) -> usize {
+        self.generating.len() + self.meshing.len()
+
+    }
+
+    /// Returns every job that has finished since the last poll, without blocking
+    pub fn poll(&mut self) -> Vec<JobResult> {
+        #[cfg(not(target_arch="wasm32"))]
+        let results: Vec<JobResult> = self.result_receiver.try_iter().collect();
+
+        #[cfg(target_arch="wasm32")]
+        let results: Vec<JobResult> = {
+            let jobs = self.queue.len().min(INLINE_JOBS_PER_POLL);
+            self.queue.drain(..jobs).map(|job| job()).collect()
+
+        };
+
+        for result in results.iter() {
+            match result {
+                JobResult::Generated { chunk_coord, .. } => self.generating.remove(chunk_coord),
+                JobResult::Meshed { chunk_coord, .. } => self.meshing.remove(chunk_coord),
+
+            };
+
+        }
+
+        results
+
+    }
+
+}
+
+impl Default for JobSystem {
+    fn default() -> Self {
+        Self::new()
+
+    }
+
+}
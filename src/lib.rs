@@ -1,11 +1,46 @@
 mod texture;
 mod camera;
-mod block;
+mod sun;
+pub mod block;
+pub mod mesh;
+pub mod world;
+pub mod registry;
+pub mod settings;
+pub mod clock;
+pub mod jobs;
+pub mod noise;
+pub mod biome;
+pub mod terrain;
+pub mod ores;
+pub mod structures;
+pub mod streaming;
+pub mod storage;
+pub mod palette;
+pub mod raycast;
+pub mod physics;
+pub mod lighting;
+pub mod frustum;
+
+use std::collections::HashMap;
+use std::sync::Arc;
 
 use camera::{Camera, CameraUniform};
+use sun::{Light, LightUniform};
 use block::*;
+use mesh::ChunkMesh;
+use world::World;
+use registry::BlockRegistry;
+use settings::Settings;
+use clock::GameClock;
+use jobs::JobSystem;
+use terrain::{HeightmapGenerator, TerrainGenerator};
+use streaming::ChunkStreamer;
+use storage::WorldStorage;
+use physics::{MovementInput, MovementMode, Player, PLAYER_EYE_HEIGHT};
 use glam::Vec3;
-use wgpu::{util::DeviceExt, Buffer, SurfaceConfiguration};
+use texture::{TextureAtlas, TextureAtlasBuilder, UvRect, MAX_ATLAS_TEXTURES};
+use glam::IVec3;
+use wgpu::{util::DeviceExt, Buffer};
 use winit::{
     event::*,
     event_loop::{ControlFlow, EventLoop},
@@ -15,68 +50,168 @@ use winit::{
 use wasm_bindgen::prelude::*;
 
 struct AppState {
-    chunk: Chunk,
+    world: World,
     camera: Camera,
+    light: Light,
+    settings: Settings,
+    clock: GameClock,
+    jobs: JobSystem,
+    streamer: ChunkStreamer,
+    player: Player,
+    // The block that's placed with right click
+    selected_block: BlockId,
     moving_forward: bool,
     moving_backward: bool,
     moving_left: bool,
     moving_right: bool,
     moving_up: bool,
     moving_down: bool,
+    // Whether the cursor is hidden and locked to the window, so mouse movement turns the camera
+    cursor_grabbed: bool,
+    // Holding a key down repeats its presses, which would keep toggling flying on and off
+    fly_key_held: bool,
 
 }
 
 impl AppState {
     fn new() -> Self {
-        let chunk = Chunk::new(Vec3::new(800.0, 800.0, 50.0));
+        let settings = Settings::default();
+        let generator: Arc<dyn TerrainGenerator> = Arc::new(HeightmapGenerator::new(settings.world_seed));
+
+        let mut camera = Camera::new();
+        // Things can be seen up to the edge of the loaded chunks
+        camera.set_view_distance(settings.render_distance as f32 * CHUNK_SIZE_AXIS as f32 * BLOCK_SIZE);
+
+        // The player starts with their eyes where the camera starts
+        let player = Player::new(camera.pos() - Vec3::Y * PLAYER_EYE_HEIGHT, MovementMode::Walk);
         
         Self {
-            chunk,
+            world: World::new(),
+            jobs: JobSystem::new(),
+            streamer: ChunkStreamer::new(generator, world_storage()),
+            player,
+            selected_block: BlockRegistry::global().id("stone").unwrap_or_default(),
+            clock: GameClock::new(settings.ticks_per_second),
+            settings,
             moving_forward: false,
             moving_backward: false,
             moving_left: false,
             moving_right: false,
             moving_up: false,
             moving_down: false,
-            camera: Camera::new(),
+            cursor_grabbed: false,
+            fly_key_held: false,
+            camera,
+            light: Light::default(),
 
         }
     }
 
+    /// Runs however many simulation steps have built up since the last frame
     fn update(&mut self) {
-        const SPEED: f32 = 0.1;
+        let steps = self.clock.tick();
+        let timestep = self.clock.timestep();
 
-        if self.moving_forward {
-            self.camera.move_forward(SPEED);
+        for _ in 0..steps {
+            self.step(timestep);
 
         }
-       
-        if self.moving_backward {
-            self.camera.move_backward(SPEED);
+
+        // Chunks are generated in the background, and show up in the world as they finish
+        self.streamer.update(&mut self.world, &mut self.jobs, self.camera.pos(), &self.settings);
+
+        // Finished chunks are picked up without waiting on the ones that are still being worked on
+        for result in self.jobs.poll() {
+            self.world.apply_job_result(result);
 
         }
 
-        if self.moving_left {
-            self.camera.move_left(SPEED);
+        self.world.queue_meshing(&mut self.jobs, self.settings.mesher, self.settings.ambient_occlusion);
+
+    }
+
+    /// Saves every loaded chunk, if saving is turned on
+    fn save(&mut self) {
+        if self.settings.save_chunks {
+            self.streamer.save_all(&mut self.world);
 
         }
-        
-        if self.moving_right {
-            self.camera.move_right(SPEED);
+
+    }
+
+    /// Advances the simulation by delta seconds
+    fn step(&mut self, delta: f32) {
+        let forward = Vec3::from(self.camera.forward_horizontal());
+        let right = Vec3::from(self.camera.right());
+        let axis = |positive: bool, negative: bool| positive as i32 as f32 - negative as i32 as f32;
+
+        let input = MovementInput {
+            horizontal: forward * axis(self.moving_forward, self.moving_backward) + right * axis(self.moving_right, self.moving_left),
+            up: self.moving_up,
+            down: self.moving_down,
+
+        };
+
+        self.player.step(&self.world, &input, delta, &self.settings);
+        self.camera.set_pos(self.player.eye_pos());
+
+    }
+
+    /// The block the camera is looking at, if it's within reach
+    fn targeted_block(&self) -> Option<raycast::RayHit> {
+        raycast::raycast(&self.world, self.camera.pos(), self.camera.forward().into(), self.settings.reach)
+
+    }
+
+    fn break_block(&mut self) {
+        if let Some(hit) = self.targeted_block() {
+            if hit.block.properties().is_breakable() {
+                self.world.set_block(hit.block_coord, BlockId::AIR);
+
+            }
 
         }
 
-        if self.moving_up {
-            self.camera.move_up(SPEED);
+    }
+
+    fn place_block(&mut self) {
+        let hit = match self.targeted_block() {
+            Some(hit) => hit,
+            None => return,
+
+        };
+
+        let block_coord = hit.block_coord + hit.normal;
+        let replaced = self.world.get_block(block_coord);
+
+        // Blocks can only go in empty space, like air or water, and not inside the player
+        let is_empty = replaced.is_some_and(|block| !block.properties().solid);
+        let inside_player = self.player.mode == MovementMode::Walk && self.player.aabb().intersects(&physics::Aabb::block(block_coord));
+
+        if hit.normal != IVec3::ZERO && is_empty && !inside_player {
+            self.world.set_block(block_coord, self.selected_block);
 
         }
-         
-        if self.moving_down {
-            self.camera.move_down(SPEED);
+
+    }
+
+    /// Makes the block being looked at the one that's placed
+    fn pick_block(&mut self) {
+        if let Some(hit) = self.targeted_block() {
+            self.selected_block = hit.block;
 
         }
 
-        self.chunk.update_graphics(self.camera.pos());
+    }
+
+    fn handle_mouse_input(&mut self, button: MouseButton) {
+        match button {
+            MouseButton::Left => self.break_block(),
+            MouseButton::Right => self.place_block(),
+            MouseButton::Middle => self.pick_block(),
+            _ => (),
+
+        };
 
     }
 
@@ -96,15 +231,166 @@ impl AppState {
             VirtualKeyCode::D | VirtualKeyCode::Right => self.moving_right = is_pressed,
             VirtualKeyCode::Space => self.moving_up = is_pressed,
             VirtualKeyCode::LShift => self.moving_down = is_pressed,
+            VirtualKeyCode::F => {
+                if is_pressed && !self.fly_key_held {
+                    self.player.toggle_mode();
+
+                }
+
+                self.fly_key_held = is_pressed;
+
+            },
             _ => (),
 
         };
 
     }
 
+    fn handle_mouse_motion(&mut self, delta: (f64, f64)) {
+        let sensitivity = self.settings.mouse_sensitivity;
+
+        if self.cursor_grabbed {
+            self.camera.rotate(delta.0 as f32 * sensitivity, -delta.1 as f32 * sensitivity);
+
+        }
+
+    }
+
+    fn set_cursor_grabbed(&mut self, window: &Window, grabbed: bool) {
+        if let Err(err) = window.set_cursor_grab(grabbed) {
+            log::warn!("Couldn't grab the cursor: {err}");
+
+        }
+
+        window.set_cursor_visible(!grabbed);
+        self.cursor_grabbed = grabbed;
+
+    }
+
+}
+
+
+
+// The GPU buffers holding a single chunk's mesh
+struct ChunkBuffers {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    // The size of each buffer in bytes
+    vertex_buffer_size: wgpu::BufferAddress,
+    index_buffer_size: wgpu::BufferAddress,
+    // The opaque indices come first in the index buffer, followed by the translucent ones
+    index_count: u32,
+    translucent_index_count: u32,
+    // The generation of the chunk mesh that's in the buffers
+    mesh_generation: u64,
+    // The block the camera was in when the translucent faces were last sorted, or None if they haven't been sorted since they were written
+    sorted_from: Option<IVec3>,
+
+}
+
+impl ChunkBuffers {
+    fn new(device: &wgpu::Device, queue: &wgpu::Queue, chunk: &Chunk) -> Self {
+        let mesh = chunk.mesh();
+        // Buffers can't be empty, so chunks without any geometry still get a small buffer
+        let buffer_size = |contents: &[u8]| (contents.len() as wgpu::BufferAddress).max(wgpu::COPY_BUFFER_ALIGNMENT);
+
+        let vertex_buffer_size = buffer_size(bytemuck::cast_slice(&mesh.vertices));
+        let index_buffer_size = buffer_size(bytemuck::cast_slice(&mesh.indices)) + buffer_size(bytemuck::cast_slice(&mesh.translucent_indices));
+
+        let vertex_buffer = device.create_buffer(
+            &wgpu::BufferDescriptor {
+                label: Some("Vertex buffer"),
+                size: vertex_buffer_size,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+                
+            }
+            
+        );
+
+        let index_buffer = device.create_buffer(
+            &wgpu::BufferDescriptor {
+                label: Some("Index buffer"),
+                size: index_buffer_size,
+                usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+                
+            }
+            
+        );
+
+        let mut chunk_buffers = Self {
+            vertex_buffer,
+            index_buffer,
+            vertex_buffer_size,
+            index_buffer_size,
+            index_count: 0,
+            translucent_index_count: 0,
+            mesh_generation: 0,
+            sorted_from: None,
+
+        };
+
+        chunk_buffers.write(queue, chunk);
+
+        chunk_buffers
+
+    }
+
+    /// Whether the mesh is small enough to be written to the existing buffers
+    fn fits(&self, mesh: &ChunkMesh) -> bool {
+        std::mem::size_of_val(mesh.vertices.as_slice()) as wgpu::BufferAddress <= self.vertex_buffer_size &&
+        (std::mem::size_of_val(mesh.indices.as_slice()) + std::mem::size_of_val(mesh.translucent_indices.as_slice())) as wgpu::BufferAddress <= self.index_buffer_size
+
+    }
+
+    fn write(&mut self, queue: &wgpu::Queue, chunk: &Chunk) {
+        let mesh = chunk.mesh();
+
+        if !mesh.vertices.is_empty() {
+            queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&mesh.vertices));
+
+        }
+
+        if !mesh.indices.is_empty() {
+            queue.write_buffer(&self.index_buffer, 0, bytemuck::cast_slice(&mesh.indices));
+
+        }
+
+        self.index_count = mesh.indices.len().try_into().unwrap();
+        self.translucent_index_count = mesh.translucent_indices.len().try_into().unwrap();
+        self.mesh_generation = chunk.mesh_generation();
+        // The translucent indices are written when they're sorted
+        self.sorted_from = None;
+
+    }
+
+    /// Sorts the translucent faces from back to front, unless the camera is still in the block they were last sorted from
+    fn sort_translucent(&mut self, queue: &wgpu::Queue, chunk: &Chunk, camera_pos: Vec3) {
+        let camera_block = World::block_coord(camera_pos);
+
+        if self.translucent_index_count == 0 || self.sorted_from == Some(camera_block) {
+            return;
+
+        }
+
+        let indices = chunk.mesh().sorted_translucent_indices(camera_pos);
+        let offset = self.index_count as wgpu::BufferAddress * std::mem::size_of::<u32>() as wgpu::BufferAddress;
+
+        queue.write_buffer(&self.index_buffer, offset, bytemuck::cast_slice(&indices));
+        self.sorted_from = Some(camera_block);
+
+    }
+
 }
 
+// How many chunks with something to draw were drawn and culled in the last frame
+#[derive(Clone, Copy, Debug, Default)]
+struct ChunkDrawStats {
+    drawn: usize,
+    culled: usize,
 
+}
 
 struct RendererState {
     surface: wgpu::Surface,
@@ -115,16 +401,21 @@ struct RendererState {
 
     render_pipeline: wgpu::RenderPipeline,
     render_pipeline_lines: wgpu::RenderPipeline,
+    translucent_pipeline: wgpu::RenderPipeline,
     fill_mode: bool,
+    depth_texture: texture::Texture,
 
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
+    chunk_buffers: HashMap<IVec3, ChunkBuffers>,
+    // The mesh generation of chunks whose meshes are empty, which don't get any buffers
+    empty_chunks: HashMap<IVec3, u64>,
+    chunk_draw_stats: ChunkDrawStats,
     diffuse_bind_group: wgpu::BindGroup,
-    diffuse_texture: texture::Texture,
+    _atlas: TextureAtlas,
 
     camera_uniform: CameraUniform,
     camera_bind_group: wgpu::BindGroup,
     camera_buffer: Buffer,
+    light_buffer: Buffer,
 }
 
 impl RendererState {
@@ -170,8 +461,31 @@ impl RendererState {
         };
         surface.configure(&device, &config);
 
-        let diffuse_bytes = include_bytes!("../assets/dirt.png");
-        let diffuse_texture = texture::Texture::from_bytes(&device, &queue, diffuse_bytes, "dirt.png").unwrap();
+        let depth_texture = texture::Texture::create_depth_texture(&device, &config, "depth_texture");
+
+        let atlas = build_texture_atlas(&device, &queue);
+
+        // The atlas rect of every texture in the block registry, indexed by the texture index stored in each vertex
+        let registry = BlockRegistry::global();
+        let texture_names = registry.texture_names();
+
+        if texture_names.len() > MAX_ATLAS_TEXTURES {
+            log::warn!("The block registry has {} textures, but only {MAX_ATLAS_TEXTURES} can be drawn", texture_names.len());
+
+        }
+
+        let mut uv_rects = atlas.uv_rects(texture_names.iter().map(String::as_str));
+        uv_rects.resize(MAX_ATLAS_TEXTURES, UvRect::default());
+
+        let atlas_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Atlas buffer"),
+                contents: bytemuck::cast_slice(&uv_rects),
+                usage: wgpu::BufferUsages::UNIFORM,
+
+            }
+
+        );
   
         let texture_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -194,6 +508,16 @@ impl RendererState {
                         ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                         count: None,
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
                 ],
                 label: Some("texture_bind_group_layout"),
             });
@@ -204,12 +528,16 @@ impl RendererState {
                 entries: &[
                     wgpu::BindGroupEntry {
                         binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
+                        resource: wgpu::BindingResource::TextureView(&atlas.texture.view),
                     },
                     wgpu::BindGroupEntry {
                         binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                    }
+                        resource: wgpu::BindingResource::Sampler(&atlas.texture.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: atlas_buffer.as_entire_binding(),
+                    },
                 ],
                 label: Some("diffuse_bind_group"),
             }
@@ -229,6 +557,16 @@ impl RendererState {
         );
 
 
+        let light_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Light buffer"),
+                contents: bytemuck::cast_slice(&[LightUniform::new(&app_state.light)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+
+            }
+
+        );
+
         let camera_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[
                 wgpu::BindGroupLayoutEntry {
@@ -240,6 +578,16 @@ impl RendererState {
                         min_binding_size: None,
                     },
                     count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 }
             ],
             label: Some("camera_bind_group_layout"),
@@ -251,6 +599,10 @@ impl RendererState {
                 wgpu::BindGroupEntry {
                     binding: 0,
                     resource: camera_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: light_buffer.as_entire_binding(),
                 }
             ],
             label: Some("camera_bind_group"),
@@ -301,7 +653,14 @@ impl RendererState {
                 // Requires Features::CONSERVATIVE_RASTERIZATION
                 conservative: false,
             },
-            depth_stencil: None,
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                // Draw fragments that are closer than what's already been drawn
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
             multisample: wgpu::MultisampleState {
                 count: 1, // 2.
                 mask: !0, // 3.
@@ -318,26 +677,27 @@ impl RendererState {
         render_pipeline_descriptor_lines.primitive.polygon_mode = wgpu::PolygonMode::Line;
     
         let render_pipeline_lines = device.create_render_pipeline(&render_pipeline_descriptor_lines);
-        
-        let vertex_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex buffer"),
-                contents: bytemuck::cast_slice(app_state.chunk.vertices()),
-                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-                
-            }
-            
-        );
 
-        let index_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Index buffer"),
-                contents: bytemuck::cast_slice(app_state.chunk.indices()),
-                usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
-                
-            }
-            
-        );
+        // Translucent faces are blended over everything behind them. They don't write depth, so translucent faces behind other translucent faces are still drawn
+        let translucent_pipeline_targets = [Some(wgpu::ColorTargetState {
+            format: config.format,
+            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+            write_mask: wgpu::ColorWrites::ALL,
+        })];
+
+        let mut translucent_pipeline_descriptor = render_pipeline_descriptor.clone();
+        translucent_pipeline_descriptor.label = Some("Translucent Render Pipeline");
+        translucent_pipeline_descriptor.fragment = Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: "fs_translucent",
+            targets: &translucent_pipeline_targets,
+        });
+        translucent_pipeline_descriptor.depth_stencil.as_mut().unwrap().depth_write_enabled = false;
+
+        let translucent_pipeline = device.create_render_pipeline(&translucent_pipeline_descriptor);
+        
+        // Chunk buffers are created when the chunks are first drawn
+        let chunk_buffers = HashMap::new();
 
         Self {
             surface,
@@ -347,12 +707,16 @@ impl RendererState {
             size,
             render_pipeline,
             render_pipeline_lines,
-            vertex_buffer,
-            index_buffer,
+            translucent_pipeline,
+            depth_texture,
+            chunk_buffers,
+            empty_chunks: HashMap::new(),
+            chunk_draw_stats: ChunkDrawStats::default(),
             diffuse_bind_group,
-            diffuse_texture,
+            _atlas: atlas,
             camera_uniform,
             camera_buffer,
+            light_buffer,
             camera_bind_group,
             // Use fill by default
             fill_mode: true,
@@ -361,11 +725,24 @@ impl RendererState {
     }
 
     fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
-        todo!()
+        self.size = new_size;
+
+        // Minimized windows have a size of 0, which the surface can't be configured with
+        if !self.is_minimized() {
+            self.config.width = new_size.width;
+            self.config.height = new_size.height;
+            self.surface.configure(&self.device, &self.config);
+
+            // The depth texture has to be the same size as the surface
+            self.depth_texture = texture::Texture::create_depth_texture(&self.device, &self.config, "depth_texture");
+
+        }
+
     }
 
-    fn input(&mut self, event: &WindowEvent) -> bool {
-        false
+    fn is_minimized(&self) -> bool {
+        self.size.width == 0 || self.size.height == 0
+
     }
 
     fn update_camera(&mut self, camera: &mut Camera) {
@@ -375,6 +752,106 @@ impl RendererState {
     }
 
     fn render(&mut self, app_state: &AppState) -> Result<(), wgpu::SurfaceError> {
+        // There's nothing to draw to until the window is restored
+        if self.is_minimized() {
+            return Ok(());
+
+        }
+
+        // Chunks that were added since the last frame need buffers, and unloaded chunks no longer do
+        self.chunk_buffers.retain(|chunk_coord, _| app_state.world.chunk(*chunk_coord).is_some());
+        self.empty_chunks.retain(|chunk_coord, _| app_state.world.chunk(*chunk_coord).is_some());
+
+        let camera_pos = app_state.camera.pos();
+        let chunk_center = |chunk_coord: IVec3| (chunk_coord.as_vec3() * CHUNK_SIZE_AXIS as f32 + Vec3::splat((CHUNK_SIZE_AXIS - 1) as f32 / 2.0)) * BLOCK_SIZE;
+
+        // Only chunks whose meshes have been rebuilt since they were last uploaded need to be uploaded again
+        let mut uploads: Vec<(IVec3, &Chunk)> = app_state.world.chunks()
+            .filter(|(chunk_coord, chunk)| {
+                let uploaded = self.chunk_buffers.get(chunk_coord).map(|chunk_buffers| chunk_buffers.mesh_generation)
+                    .or_else(|| self.empty_chunks.get(chunk_coord).copied());
+
+                uploaded != Some(chunk.mesh_generation())
+
+            })
+            .map(|(chunk_coord, chunk)| (*chunk_coord, chunk))
+            .collect();
+
+        // Empty meshes have nothing to upload, so they're dealt with straight away without counting towards the limit
+        uploads.retain(|(chunk_coord, chunk)| {
+            if !chunk.mesh().vertices.is_empty() {
+                return true;
+
+            }
+
+            self.chunk_buffers.remove(chunk_coord);
+            self.empty_chunks.insert(*chunk_coord, chunk.mesh_generation());
+
+            false
+
+        });
+
+        // The nearest chunks go first, which includes the ones being edited, and any left over are uploaded over the next few frames,
+        // keeping their old meshes until then
+        uploads.sort_unstable_by(|(a, _), (b, _)| chunk_center(*a).distance_squared(camera_pos).total_cmp(&chunk_center(*b).distance_squared(camera_pos)));
+
+        for (chunk_coord, chunk) in uploads.into_iter().take(app_state.settings.max_chunk_uploads_per_frame) {
+            self.empty_chunks.remove(&chunk_coord);
+
+            match self.chunk_buffers.get_mut(&chunk_coord) {
+                // Update the chunk's buffers, or replace them if the mesh has outgrown them
+                Some(chunk_buffers) if chunk_buffers.fits(chunk.mesh()) => chunk_buffers.write(&self.queue, chunk),
+                _ => {
+                    self.chunk_buffers.insert(chunk_coord, ChunkBuffers::new(&self.device, &self.queue, chunk));
+
+                },
+
+            };
+
+        }
+
+        let frustum = app_state.camera.frustum();
+
+        // Chunks that are entirely outside of the camera's view aren't drawn
+        let mut visible_chunks = Vec::new();
+        self.chunk_draw_stats = ChunkDrawStats::default();
+
+        for (chunk_coord, chunk_buffers) in self.chunk_buffers.iter() {
+            let chunk = match app_state.world.chunk(*chunk_coord) {
+                Some(chunk) if chunk_buffers.index_count + chunk_buffers.translucent_index_count > 0 => chunk,
+                _ => continue,
+
+            };
+
+            match frustum.intersects_aabb(&chunk.aabb()) {
+                true => {
+                    visible_chunks.push(*chunk_coord);
+                    self.chunk_draw_stats.drawn += 1;
+
+                },
+                false => self.chunk_draw_stats.culled += 1,
+
+            };
+
+        }
+
+        for chunk_coord in visible_chunks.iter() {
+            if let (Some(chunk_buffers), Some(chunk)) = (self.chunk_buffers.get_mut(chunk_coord), app_state.world.chunk(*chunk_coord)) {
+                chunk_buffers.sort_translucent(&self.queue, chunk, camera_pos);
+
+            }
+
+        }
+
+        // Translucent chunks are drawn from back to front too, so the chunks in front blend over the ones behind them
+        let mut translucent_chunks: Vec<(f32, &ChunkBuffers)> = visible_chunks.iter()
+            .map(|chunk_coord| (chunk_coord, &self.chunk_buffers[chunk_coord]))
+            .filter(|(_, chunk_buffers)| chunk_buffers.translucent_index_count > 0)
+            .map(|(chunk_coord, chunk_buffers)| (chunk_center(*chunk_coord).distance_squared(camera_pos), chunk_buffers))
+            .collect();
+
+        translucent_chunks.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
         let output = self.surface.get_current_texture()?;
         let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
         let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
@@ -404,7 +881,14 @@ impl RendererState {
                         }
                     })
                 ],
-                depth_stencil_attachment: None,
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_texture.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
             });
 
 
@@ -416,17 +900,35 @@ impl RendererState {
             render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
             render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
 
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            for chunk_buffers in visible_chunks.iter().map(|chunk_coord| &self.chunk_buffers[chunk_coord]).filter(|chunk_buffers| chunk_buffers.index_count > 0) {
+                render_pass.set_vertex_buffer(0, chunk_buffers.vertex_buffer.slice(..));
+                render_pass.set_index_buffer(chunk_buffers.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+
+                render_pass.draw_indexed(0..chunk_buffers.index_count, 0, 0..1);
+
+            }
+
+            // Translucent faces go last, so everything behind them has already been drawn
+            if self.fill_mode {
+                render_pass.set_pipeline(&self.translucent_pipeline);
+
+            }
+
+            for (_, chunk_buffers) in translucent_chunks {
+                let translucent_indices = chunk_buffers.index_count..chunk_buffers.index_count + chunk_buffers.translucent_index_count;
 
-            render_pass.draw_indexed(0..(app_state.chunk.indices().len().try_into().unwrap()), 0, 0..1);
+                render_pass.set_vertex_buffer(0, chunk_buffers.vertex_buffer.slice(..));
+                render_pass.set_index_buffer(chunk_buffers.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+
+                render_pass.draw_indexed(translucent_indices, 0, 0..1);
+
+            }
             
         }
 
-        // Update the vertex buffer
-        self.queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(app_state.chunk.vertices()));
         // Update the camera position
         self.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(self.camera_uniform.view_proj()));
+        self.queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&[LightUniform::new(&app_state.light)]));
         self.queue.submit(std::iter::once(encoder.finish()));
 
         output.present();
@@ -435,6 +937,57 @@ impl RendererState {
     }
 }
 
+// The block textures compiled into the game, for when the assets directory can't be read
+const BUILTIN_TEXTURES: &[(&str, &[u8])] = &[
+    ("dirt", include_bytes!("../assets/dirt.png")),
+    ("grass_top", include_bytes!("../assets/grass_top.png")),
+    ("grass_side", include_bytes!("../assets/grass_side.png")),
+    ("stone", include_bytes!("../assets/stone.png")),
+    ("sand", include_bytes!("../assets/sand.png")),
+    ("water", include_bytes!("../assets/water.png")),
+    ("glass", include_bytes!("../assets/glass.png")),
+    ("glowstone", include_bytes!("../assets/glowstone.png")),
+    ("snow", include_bytes!("../assets/snow.png")),
+    ("snow_side", include_bytes!("../assets/snow_side.png")),
+    ("bedrock", include_bytes!("../assets/bedrock.png")),
+    ("coal_ore", include_bytes!("../assets/coal_ore.png")),
+    ("iron_ore", include_bytes!("../assets/iron_ore.png")),
+    ("gold_ore", include_bytes!("../assets/gold_ore.png")),
+    ("log_top", include_bytes!("../assets/log_top.png")),
+    ("log_side", include_bytes!("../assets/log_side.png")),
+    ("leaves", include_bytes!("../assets/leaves.png")),
+    ("planks", include_bytes!("../assets/planks.png")),
+];
+
+fn build_texture_atlas(device: &wgpu::Device, queue: &wgpu::Queue) -> TextureAtlas {
+    let mut atlas_builder = TextureAtlasBuilder::new();
+
+    for (name, bytes) in BUILTIN_TEXTURES {
+        atlas_builder.add_bytes(name, bytes).unwrap();
+
+    }
+
+    // Textures on disk replace the builtin ones, so they can be changed without recompiling
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Err(err) = atlas_builder.add_dir("assets") {
+        log::warn!("Couldn't load textures from assets, using the builtin textures: {err:?}");
+
+    }
+
+    atlas_builder.build(device, queue, "Texture atlas").unwrap()
+
+}
+
+// Where chunks are saved. The browser has no files to write to, so the world is only kept until the page is closed there
+fn world_storage() -> Box<dyn WorldStorage> {
+    #[cfg(not(target_arch = "wasm32"))]
+    return Box::new(storage::FileStorage::new("saves/world"));
+
+    #[cfg(target_arch = "wasm32")]
+    return Box::new(storage::MemoryStorage::new());
+
+}
+
 #[cfg_attr(target_arch="wasm32", wasm_bindgen(start))]
 pub async fn main() {
     #[cfg(not(target_arch = "wasm32"))]
@@ -446,6 +999,20 @@ pub async fn main() {
         console_log::init_with_level(log::Level::Warn).expect("Couldn't initialize logger");
     };
 
+    // Load the block types from disk so they can be changed without recompiling
+    #[cfg(not(target_arch = "wasm32"))]
+    match BlockRegistry::load("assets/blocks.toml") {
+        Ok(registry) => {
+            if BlockRegistry::set_global(registry).is_err() {
+                log::warn!("The block registry was used before it was loaded");
+
+            }
+
+        },
+        Err(err) => log::warn!("Couldn't load assets/blocks.toml, using the builtin block registry: {err:?}"),
+
+    };
+
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new().build(&event_loop).unwrap();
 
@@ -475,7 +1042,20 @@ pub async fn main() {
         Event::RedrawRequested(window_id) if window_id == window.id() => {
             app_state.update();
             state.update_camera(&mut app_state.camera);
-            state.render(&app_state).unwrap();
+
+            match state.render(&app_state) {
+                Ok(()) => (),
+                // Reconfigure the surface if it's been lost or no longer matches the window
+                Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => state.resize(state.size),
+                Err(wgpu::SurfaceError::OutOfMemory) => {
+                    log::error!("Out of GPU memory, exiting");
+                    *control_flow = ControlFlow::Exit;
+
+                },
+                // Skip the frame and try again next time
+                Err(err) => log::warn!("Couldn't render frame: {err:?}"),
+
+            };
 
         },
         Event::MainEventsCleared => {
@@ -487,19 +1067,58 @@ pub async fn main() {
             ref event,
             window_id,
         } if window_id == window.id() => match event {
-            WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit, 
+            WindowEvent::CloseRequested => {
+                app_state.save();
+                *control_flow = ControlFlow::Exit;
+
+            },
+            WindowEvent::Resized(physical_size) => {
+                state.resize(*physical_size);
+                app_state.camera.update_aspect(&state.config);
+
+            },
+            WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                state.resize(**new_inner_size);
+                app_state.camera.update_aspect(&state.config);
+
+            },
             WindowEvent::KeyboardInput { device_id: _, input, is_synthetic: _ } => {
                 if input.virtual_keycode == Some(VirtualKeyCode::LAlt) {
                     state.fill_mode = input.state != ElementState::Pressed;
 
                 }
 
+                if input.virtual_keycode == Some(VirtualKeyCode::F3) && input.state == ElementState::Pressed {
+                    let stats = state.chunk_draw_stats;
+                    log::info!("Drew {} chunks and culled {} chunks last frame", stats.drawn, stats.culled);
+
+                }
+
+                // Release the cursor so it can leave the window
+                if input.virtual_keycode == Some(VirtualKeyCode::Escape) && input.state == ElementState::Pressed {
+                    app_state.set_cursor_grabbed(&window, false);
+
+                }
+
                 app_state.handle_input(input);
 
             },
+            WindowEvent::MouseInput { state: ElementState::Pressed, button: MouseButton::Left, .. } if !app_state.cursor_grabbed => {
+                app_state.set_cursor_grabbed(&window, true);
+
+            },
+            WindowEvent::MouseInput { state: ElementState::Pressed, button, .. } if app_state.cursor_grabbed => {
+                app_state.handle_mouse_input(*button);
+
+            },
+            WindowEvent::Focused(false) => app_state.set_cursor_grabbed(&window, false),
             _ => (),
 
         },
+        Event::DeviceEvent {
+            event: DeviceEvent::MouseMotion { delta },
+            ..
+        } => app_state.handle_mouse_motion(delta),
         _ => {}
     });
 }
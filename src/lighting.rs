@@ -0,0 +1,899 @@
+use std::collections::VecDeque;
+
+use glam::{IVec2, IVec3};
+
+use crate::block::{BlockId, Chunk, Face, LocalPos, CHUNK_SIZE_AXIS};
+use crate::world::World;
+
+/// The brightest a light level can be
+pub const MAX_LIGHT: u8 = 15;
+
+/// The two kinds of light a block can be lit by
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LightChannel {
+    /// Light from the sky, which shines straight down without getting any dimmer
+    Sky,
+    /// Light given off by blocks like glowstone
+    Block,
+
+}
+
+impl LightChannel {
+    pub const ALL: [LightChannel; 2] = [LightChannel::Sky, LightChannel::Block];
+
+}
+
+/// A block's sky light and block light, each from 0 to MAX_LIGHT, packed into a byte
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct LightLevel(u8);
+
+impl LightLevel {
+    pub const DARK: LightLevel = LightLevel(0);
+
+    pub const fn new(sky: u8, block: u8) -> Self {
+        Self((sky & 0xF) << 4 | (block & 0xF))
+
+    }
+
+    pub const fn sky(self) -> u8 {
+        self.0 >> 4
+
+    }
+
+    pub const fn block(self) -> u8 {
+        self.0 & 0xF
+
+    }
+
+    pub const fn get(self, channel: LightChannel) -> u8 {
+        match channel {
+            LightChannel::Sky => self.sky(),
+            LightChannel::Block => self.block(),
+
+        }
+
+    }
+
+    /// Returns a copy with one channel changed
+    pub const fn with(self, channel: LightChannel, level: u8) -> Self {
+        match channel {
+            LightChannel::Sky => Self::new(level, self.block()),
+            LightChannel::Block => Self::new(self.sky(), level),
+
+        }
+
+    }
+
+}
+
+/// A copy of the light and blocks in a chunk and in the layer of blocks around it, so the faces on the edge of the chunk
+/// can be lit and hidden by their neighbors while it's meshed away from the rest of the world
+#[derive(Clone, Debug)]
+pub struct ChunkLight<const N: usize = CHUNK_SIZE_AXIS> {
+    // Both are indexed like a chunk that's N + 2 blocks long, with the chunk's first block at (1, 1, 1)
+    levels: Vec<LightLevel>,
+    // None where the block's chunk isn't loaded
+    blocks: Vec<Option<BlockId>>,
+
+}
+
+impl<const N: usize> ChunkLight<N> {
+    const SIDE: usize = N + 2;
+
+    /// Light where every block is dark and unloaded
+    pub fn dark() -> Self {
+        Self {
+            levels: vec![LightLevel::DARK; Self::SIDE.pow(3)],
+            blocks: vec![None; Self::SIDE.pow(3)],
+
+        }
+
+    }
+
+    /// The light and blocks in a chunk, with the blocks around it left dark and unloaded
+    pub fn from_chunk(chunk: &Chunk<N>) -> Self {
+        let mut light = Self::dark();
+
+        for local_pos in LocalPos::all::<N>() {
+            light.set(local_pos.as_ivec3(), chunk.get_light(local_pos));
+            light.set_block(local_pos.as_ivec3(), Some(chunk.get_block(local_pos)));
+
+        }
+
+        light
+
+    }
+
+    fn index(pos: IVec3) -> Option<usize> {
+        let padded = pos + IVec3::ONE;
+
+        match padded.cmpge(IVec3::ZERO).all() && padded.cmplt(IVec3::splat(Self::SIDE as i32)).all() {
+            true => Some(padded.x as usize + Self::SIDE * (padded.y as usize + Self::SIDE * padded.z as usize)),
+            false => None,
+
+        }
+
+    }
+
+    /// The light at a position relative to the chunk's first block, which can be from -1 to N on each axis.
+    /// Anywhere further away is dark
+    pub fn get(&self, pos: IVec3) -> LightLevel {
+        Self::index(pos).map(|index| self.levels[index]).unwrap_or_default()
+
+    }
+
+    pub fn set(&mut self, pos: IVec3, level: LightLevel) {
+        if let Some(index) = Self::index(pos) {
+            self.levels[index] = level;
+
+        }
+
+    }
+
+    /// The block at a position relative to the chunk's first block, or None if it isn't loaded or is too far away
+    pub fn block(&self, pos: IVec3) -> Option<BlockId> {
+        Self::index(pos).and_then(|index| self.blocks[index])
+
+    }
+
+    pub fn set_block(&mut self, pos: IVec3, block: Option<BlockId>) {
+        if let Some(index) = Self::index(pos) {
+            self.blocks[index] = block;
+
+        }
+
+    }
+
+    /// Whether the block at a position relative to the chunk's first block casts ambient occlusion onto the faces next to it
+    pub fn is_opaque(&self, pos: IVec3) -> bool {
+        self.block(pos).is_some_and(|block| !block.properties().transparent)
+
+    }
+
+}
+
+impl ChunkLight {
+    /// Copies the light and blocks in a loaded chunk and the chunks around it, or returns None if the chunk isn't loaded.
+    /// Blocks in chunks that aren't loaded are left dark and unloaded
+    pub fn from_world(world: &World, chunk_coord: IVec3) -> Option<Self> {
+        let mut light = Self::from_chunk(world.chunk(chunk_coord)?);
+        let n = CHUNK_SIZE_AXIS as i32;
+        let origin = chunk_coord * n;
+
+        // Every block in the layer around the chunk, including along its edges and at its corners
+        for z in -1..=n {
+            for y in -1..=n {
+                for x in -1..=n {
+                    let pos = IVec3::new(x, y, z);
+
+                    if pos.cmpge(IVec3::ZERO).all() && pos.cmplt(IVec3::splat(n)).all() {
+                        continue;
+
+                    }
+
+                    if let (Some(block), Some(level)) = (world.get_block(origin + pos), light_at(world, origin + pos)) {
+                        light.set(pos, level);
+                        light.set_block(pos, Some(block));
+
+                    }
+
+                }
+
+            }
+
+        }
+
+        Some(light)
+
+    }
+
+}
+
+/// The block y of the highest block in each column of a chunk that sky light can't get through, indexed by the column's local x and z.
+/// Sky light shines on every transparent block above it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SkyHeights(Vec<i32>);
+
+impl SkyHeights {
+    /// Sky heights where every column is blocked at the same height
+    pub fn filled(height: i32) -> Self {
+        Self(vec![height; CHUNK_SIZE_AXIS * CHUNK_SIZE_AXIS])
+
+    }
+
+    fn index(x: u32, z: u32) -> usize {
+        x as usize + CHUNK_SIZE_AXIS * z as usize
+
+    }
+
+    pub fn get(&self, x: u32, z: u32) -> i32 {
+        self.0[Self::index(x, z)]
+
+    }
+
+    pub fn set(&mut self, x: u32, z: u32, height: i32) {
+        self.0[Self::index(x, z)] = height;
+
+    }
+
+}
+
+// Somewhere light can spread through, which is either the whole world or a single chunk that's being lit by itself
+trait LightVolume {
+    // None where the block isn't loaded
+    fn light(&self, block_coord: IVec3) -> Option<LightLevel>;
+    fn set_light(&mut self, block_coord: IVec3, channel: LightChannel, level: u8);
+    // None where the block isn't loaded, which light can't get into
+    fn block(&self, block_coord: IVec3) -> Option<BlockId>;
+    // The sky height of the column at block x and z, or None if it isn't known
+    fn sky_height(&self, x: i32, z: i32) -> Option<i32>;
+
+}
+
+impl LightVolume for World {
+    fn light(&self, block_coord: IVec3) -> Option<LightLevel> {
+        light_at(self, block_coord)
+
+    }
+
+    fn set_light(&mut self, block_coord: IVec3, channel: LightChannel, level: u8) {
+        set_light_at(self, block_coord, channel, level);
+
+    }
+
+    fn block(&self, block_coord: IVec3) -> Option<BlockId> {
+        self.get_block(block_coord)
+
+    }
+
+    fn sky_height(&self, x: i32, z: i32) -> Option<i32> {
+        World::sky_height(self, x, z)
+
+    }
+
+}
+
+// A chunk being lit by itself, where everything around it counts as unloaded
+struct LoneChunk<'a> {
+    chunk: &'a mut Chunk,
+    // The block coordinate of the chunk's first block
+    origin: IVec3,
+    sky_heights: &'a SkyHeights,
+
+}
+
+impl LoneChunk<'_> {
+    fn local_pos(&self, block_coord: IVec3) -> Option<LocalPos> {
+        let pos = block_coord - self.origin;
+
+        match pos.cmpge(IVec3::ZERO).all() && pos.cmplt(IVec3::splat(CHUNK_SIZE_AXIS as i32)).all() {
+            true => Some(pos.as_uvec3().into()),
+            false => None,
+
+        }
+
+    }
+
+}
+
+impl LightVolume for LoneChunk<'_> {
+    fn light(&self, block_coord: IVec3) -> Option<LightLevel> {
+        self.local_pos(block_coord).map(|local_pos| self.chunk.get_light(local_pos))
+
+    }
+
+    fn set_light(&mut self, block_coord: IVec3, channel: LightChannel, level: u8) {
+        if let Some(local_pos) = self.local_pos(block_coord) {
+            self.chunk.set_light(local_pos, self.chunk.get_light(local_pos).with(channel, level));
+
+        }
+
+    }
+
+    fn block(&self, block_coord: IVec3) -> Option<BlockId> {
+        self.local_pos(block_coord).map(|local_pos| self.chunk.get_block(local_pos))
+
+    }
+
+    fn sky_height(&self, x: i32, z: i32) -> Option<i32> {
+        // Any y works, since only the column matters
+        self.local_pos(IVec3::new(x, self.origin.y, z)).map(|local_pos| self.sky_heights.get(local_pos.x, local_pos.z))
+
+    }
+
+}
+
+// Every block on one side of a chunk, paired with the block next to it in the neighboring chunk
+fn chunk_border(origin: IVec3, face: Face) -> impl Iterator<Item = (IVec3, IVec3)> {
+    let n = CHUNK_SIZE_AXIS as i32;
+    let axis = face.axis();
+    let u_axis = (axis + 1) % 3;
+    let v_axis = (axis + 2) % 3;
+
+    (0..n * n).map(move |i| {
+        let mut inside = IVec3::ZERO;
+        inside[axis] = if face.is_positive() { n - 1 } else { 0 };
+        inside[u_axis] = i % n;
+        inside[v_axis] = i / n;
+
+        (origin + inside, origin + inside + face.normal())
+
+    })
+
+}
+
+fn light_at(world: &World, block_coord: IVec3) -> Option<LightLevel> {
+    let (chunk_coord, local_pos) = World::chunk_and_local_pos(block_coord);
+
+    world.chunk(chunk_coord).map(|chunk| chunk.get_light(local_pos))
+
+}
+
+// Changes one channel of a block's light. Chunks that touch the block are remeshed, since their faces can be lit by it
+fn set_light_at(world: &mut World, block_coord: IVec3, channel: LightChannel, level: u8) {
+    let (chunk_coord, local_pos) = World::chunk_and_local_pos(block_coord);
+
+    let changed = match world.chunk_mut(chunk_coord) {
+        Some(chunk) => chunk.set_light(local_pos, chunk.get_light(local_pos).with(channel, level)),
+        None => false,
+
+    };
+
+    if changed {
+        world.mark_dirty_around(block_coord);
+
+    }
+
+}
+
+// Whether light can pass into a block, which needs it to be loaded and transparent
+fn lets_light_through(volume: &impl LightVolume, block_coord: IVec3) -> bool {
+    volume.block(block_coord).is_some_and(|block| block.properties().transparent)
+
+}
+
+// The light a block gives off by itself. Transparent blocks above their column's sky height are lit by the sky
+fn emission(volume: &impl LightVolume, block_coord: IVec3, channel: LightChannel) -> u8 {
+    match channel {
+        LightChannel::Block => volume.block(block_coord).map_or(0, |block| block.properties().light_emission),
+        LightChannel::Sky => {
+            let under_sky = volume.sky_height(block_coord.x, block_coord.z).is_some_and(|height| block_coord.y > height);
+
+            match under_sky && lets_light_through(volume, block_coord) {
+                true => MAX_LIGHT,
+                false => 0,
+
+            }
+
+        },
+
+    }
+
+}
+
+// How bright light is after spreading from a block with the given level towards face
+fn spread_level(channel: LightChannel, level: u8, face: Face) -> u8 {
+    match channel == LightChannel::Sky && face == Face::YNeg && level == MAX_LIGHT {
+        true => MAX_LIGHT,
+        false => level.saturating_sub(1),
+
+    }
+
+}
+
+// Flood fills light outwards from every block in the queue
+fn spread_light(volume: &mut impl LightVolume, channel: LightChannel, mut queue: VecDeque<IVec3>) {
+    while let Some(block_coord) = queue.pop_front() {
+        let level = match volume.light(block_coord) {
+            Some(light) => light.get(channel),
+            None => continue,
+
+        };
+
+        for face in Face::ALL {
+            let neighbor = block_coord + face.normal();
+            let spread = spread_level(channel, level, face);
+
+            if spread == 0 || !lets_light_through(volume, neighbor) {
+                continue;
+
+            }
+
+            if volume.light(neighbor).is_some_and(|light| light.get(channel) < spread) {
+                volume.set_light(neighbor, channel, spread);
+                queue.push_back(neighbor);
+
+            }
+
+        }
+
+    }
+
+}
+
+// Darkens every block that was lit by the removed blocks, which are given with the level they used to have.
+// Returns the blocks lit from elsewhere that bordered the darkened area, which need to spread their light back into it
+fn remove_light(volume: &mut impl LightVolume, channel: LightChannel, mut removed: VecDeque<(IVec3, u8)>) -> VecDeque<IVec3> {
+    let mut refill = VecDeque::new();
+
+    while let Some((block_coord, level)) = removed.pop_front() {
+        for face in Face::ALL {
+            let neighbor = block_coord + face.normal();
+
+            let neighbor_level = match volume.light(neighbor) {
+                Some(light) => light.get(channel),
+                None => continue,
+
+            };
+
+            if neighbor_level == 0 {
+                continue;
+
+            }
+
+            // Anything dimmer than the removed block could have been lit by it, as could sky light that shone straight down from it
+            if neighbor_level < level || (neighbor_level == MAX_LIGHT && spread_level(channel, level, face) == MAX_LIGHT) {
+                volume.set_light(neighbor, channel, 0);
+                removed.push_back((neighbor, neighbor_level));
+
+                let emitted = emission(volume, neighbor, channel);
+
+                if emitted > 0 {
+                    volume.set_light(neighbor, channel, emitted);
+                    refill.push_back(neighbor);
+
+                }
+
+            } else {
+                refill.push_back(neighbor);
+
+            }
+
+        }
+
+    }
+
+    refill
+
+}
+
+// Takes away the light of the blocks and everything they lit, then lets them give off light again and the light around them fill back in.
+// This is how light is fixed after blocks start or stop giving it off, or start or stop letting it through
+fn relight(world: &mut World, channel: LightChannel, blocks: &[IVec3]) {
+    let mut removed = VecDeque::new();
+
+    for &block_coord in blocks {
+        if let Some(light) = light_at(world, block_coord) {
+            set_light_at(world, block_coord, channel, 0);
+            removed.push_back((block_coord, light.get(channel)));
+
+        }
+
+    }
+
+    let mut refill = remove_light(world, channel, removed);
+
+    for &block_coord in blocks {
+        let emitted = emission(world, block_coord, channel);
+
+        if emitted > 0 {
+            set_light_at(world, block_coord, channel, emitted);
+            refill.push_back(block_coord);
+
+        }
+
+    }
+
+    spread_light(world, channel, refill);
+
+}
+
+/// Lights a chunk by itself as if nothing around it were loaded, with sky light shining down to the given sky heights.
+/// This is most of the work of lighting a chunk, so it can be done away from the world before light_new_chunk finishes it off
+pub fn light_chunk(chunk_coord: IVec3, chunk: &mut Chunk, sky_heights: &SkyHeights) {
+    let origin = chunk_coord * CHUNK_SIZE_AXIS as i32;
+    let has_emitters = chunk.palette().iter().any(|block| block.properties().light_emission > 0);
+    let mut volume = LoneChunk { chunk, origin, sky_heights };
+
+    for channel in LightChannel::ALL {
+        if channel == LightChannel::Block && !has_emitters {
+            continue;
+
+        }
+
+        let mut queue = VecDeque::new();
+
+        for local_pos in LocalPos::all::<CHUNK_SIZE_AXIS>() {
+            let block_coord = origin + local_pos.as_ivec3();
+            let emitted = emission(&volume, block_coord, channel);
+
+            if emitted > 0 {
+                volume.set_light(block_coord, channel, emitted);
+                queue.push_back(block_coord);
+
+            }
+
+        }
+
+        spread_light(&mut volume, channel, queue);
+
+    }
+
+}
+
+/// Finishes lighting a chunk that has just been added to the world, after light_chunk lit it with lit_with as its sky heights.
+/// Light spreads across its borders, and columns are lit again where the world's sky heights are now different from lit_with
+/// or from what they were before the chunk was added, given by previous
+pub fn light_new_chunk(world: &mut World, chunk_coord: IVec3, lit_with: &SkyHeights, previous: &SkyHeights) {
+    let origin = chunk_coord * CHUNK_SIZE_AXIS as i32;
+
+    for z in 0..CHUNK_SIZE_AXIS as u32 {
+        for x in 0..CHUNK_SIZE_AXIS as u32 {
+            let column = IVec2::new(origin.x + x as i32, origin.z + z as i32);
+
+            let heights = match world.sky_height(column.x, column.y) {
+                Some(current) => [lit_with.get(x, z), previous.get(x, z), current],
+                None => continue,
+
+            };
+
+            let (lowest, highest) = (heights.into_iter().min().unwrap(), heights.into_iter().max().unwrap());
+
+            if lowest != highest {
+                sky_height_changed(world, column.x, column.y, lowest, highest);
+
+            }
+
+        }
+
+    }
+
+    for channel in LightChannel::ALL {
+        let mut queue = VecDeque::new();
+
+        // Only blocks that brighten the block across the border from them need to spread their light
+        for face in Face::ALL {
+            for (inside, outside) in chunk_border(origin, face) {
+                for (from, to, towards) in [(inside, outside, face), (outside, inside, face.opposite())] {
+                    let spread = match light_at(world, from) {
+                        Some(light) => spread_level(channel, light.get(channel), towards),
+                        None => continue,
+
+                    };
+
+                    if spread > 0 && lets_light_through(world, to) && light_at(world, to).is_some_and(|light| light.get(channel) < spread) {
+                        queue.push_back(from);
+
+                    }
+
+                }
+
+            }
+
+        }
+
+        spread_light(world, channel, queue);
+
+    }
+
+}
+
+/// Takes the light that a chunk which has just been removed from the world spread into the chunks around it back out of them
+pub fn light_removed_chunk(world: &mut World, chunk_coord: IVec3, chunk: &Chunk) {
+    let origin = chunk_coord * CHUNK_SIZE_AXIS as i32;
+
+    for channel in LightChannel::ALL {
+        let mut blocks = Vec::new();
+
+        for face in Face::ALL {
+            for (inside, outside) in chunk_border(origin, face) {
+                let inside_level = chunk.get_light((inside - origin).as_uvec3().into()).get(channel);
+
+                let outside_level = match light_at(world, outside) {
+                    Some(light) => light.get(channel),
+                    None => continue,
+
+                };
+
+                // Only blocks exactly as bright as the light coming out of the chunk could have been lit by it, unless they light themselves that brightly
+                if outside_level > 0 && spread_level(channel, inside_level, face) == outside_level && emission(world, outside, channel) < outside_level {
+                    blocks.push(outside);
+
+                }
+
+            }
+
+        }
+
+        relight(world, channel, &blocks);
+
+    }
+
+}
+
+/// Relights the blocks in the column at block x and z that sky light started or stopped shining on when its sky height changed from old to new
+pub fn sky_height_changed(world: &mut World, x: i32, z: i32, old: i32, new: i32) {
+    let blocks: Vec<IVec3> = (old.min(new) + 1..=old.max(new)).map(|y| IVec3::new(x, y, z)).collect();
+
+    relight(world, LightChannel::Sky, &blocks);
+
+}
+
+/// Updates the light around a block that has just changed
+pub fn update_light(world: &mut World, block_coord: IVec3) {
+    for channel in LightChannel::ALL {
+        relight(world, channel, &[block_coord]);
+
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::registry::BlockRegistry;
+    use crate::terrain::{HeightmapGenerator, TerrainGenerator};
+
+    use super::*;
+
+    fn block(name: &str) -> BlockId {
+        BlockRegistry::global().id(name).unwrap()
+
+    }
+
+    fn light(world: &World, block_coord: IVec3) -> LightLevel {
+        light_at(world, block_coord).unwrap()
+
+    }
+
+    // Every block in the loaded chunks
+    fn block_coords(world: &World) -> Vec<IVec3> {
+        world.chunks()
+            .flat_map(|(chunk_coord, _)| LocalPos::all::<CHUNK_SIZE_AXIS>().map(move |local_pos| *chunk_coord * CHUNK_SIZE_AXIS as i32 + local_pos.as_ivec3()))
+            .collect()
+
+    }
+
+    fn distance(a: IVec3, b: IVec3) -> i32 {
+        let offset = (a - b).abs();
+        offset.x + offset.y + offset.z
+
+    }
+
+    // Block light spreading out from a single emitter through air, which only gets dimmer with distance
+    fn assert_lit_by(world: &World, emitter: IVec3) {
+        for block_coord in block_coords(world) {
+            let expected = (MAX_LIGHT as i32 - distance(block_coord, emitter)).max(0) as u8;
+            assert_eq!(light(world, block_coord).block(), expected, "block light at {block_coord}");
+
+        }
+
+    }
+
+    fn assert_no_block_light(world: &World) {
+        for block_coord in block_coords(world) {
+            assert_eq!(light(world, block_coord).block(), 0, "block light at {block_coord}");
+
+        }
+
+    }
+
+    // A world of stone with a shaft dug down through it from the top, crossing the border between its two chunks
+    const SHAFT_TOP: IVec3 = IVec3::new(8, 15, 8);
+    const SHAFT_DEPTH: i32 = 24;
+
+    fn shaft() -> impl Iterator<Item = IVec3> {
+        (0..SHAFT_DEPTH).map(|depth| SHAFT_TOP - IVec3::Y * depth)
+
+    }
+
+    #[test]
+    fn a_shaft_opened_to_the_sky_is_lit_all_the_way_down() {
+        let mut world = World::with_chunks(IVec3::NEG_Y, IVec3::ZERO, block("stone"));
+
+        for block_coord in shaft() {
+            world.set_block(block_coord, BlockId::AIR);
+
+        }
+
+        for block_coord in shaft() {
+            assert_eq!(light(&world, block_coord).sky(), MAX_LIGHT, "sky light at {block_coord}");
+
+        }
+
+        // Digging out the side of the shaft lets light in sideways
+        let side = SHAFT_TOP - IVec3::Y * (SHAFT_DEPTH - 1) + IVec3::X;
+        world.set_block(side, BlockId::AIR);
+        assert_eq!(light(&world, side).sky(), MAX_LIGHT - 1);
+
+        // Covering it back up shuts all of it out again
+        world.set_block(SHAFT_TOP, block("stone"));
+
+        for block_coord in shaft().skip(1).chain([side]) {
+            assert_eq!(light(&world, block_coord).sky(), 0, "sky light at {block_coord}");
+
+        }
+
+    }
+
+    #[test]
+    fn transparent_blocks_let_sky_light_down_the_shaft() {
+        let mut world = World::with_chunks(IVec3::NEG_Y, IVec3::ZERO, block("stone"));
+
+        for block_coord in shaft() {
+            world.set_block(block_coord, BlockId::AIR);
+
+        }
+
+        world.set_block(SHAFT_TOP, block("glass"));
+
+        for block_coord in shaft() {
+            assert_eq!(light(&world, block_coord).sky(), MAX_LIGHT, "sky light at {block_coord}");
+
+        }
+
+    }
+
+    #[test]
+    fn pockets_below_unloaded_chunks_are_not_lit_by_the_sky() {
+        // An empty chunk that the generator says is underground, with nothing loaded above it
+        let chunk_coord = IVec3::new(0, -3, 0);
+        let sky_heights = SkyHeights::filled(20);
+        let mut chunk = Chunk::new(chunk_coord);
+        light_chunk(chunk_coord, &mut chunk, &sky_heights);
+
+        let mut world = World::new();
+        world.insert_lit_chunk(chunk_coord, chunk, &sky_heights);
+
+        for block_coord in block_coords(&world) {
+            assert_eq!(light(&world, block_coord).sky(), 0, "sky light at {block_coord}");
+
+        }
+
+    }
+
+    #[test]
+    fn glowstone_light_falls_off_by_one_per_block_across_chunk_borders() {
+        let mut world = World::with_chunks(IVec3::ZERO, IVec3::X, BlockId::AIR);
+        // Close enough to the border that its light reaches well into the next chunk
+        let emitter = IVec3::new(14, 8, 8);
+
+        world.set_block(emitter, block("glowstone"));
+        assert_lit_by(&world, emitter);
+
+    }
+
+    #[test]
+    fn glowstone_light_spreads_into_chunks_loaded_after_it() {
+        let mut world = World::with_chunks(IVec3::ZERO, IVec3::ZERO, BlockId::AIR);
+        let emitter = IVec3::new(14, 8, 8);
+
+        world.set_block(emitter, block("glowstone"));
+        world.insert_chunk(IVec3::X, Chunk::new(IVec3::X));
+        assert_lit_by(&world, emitter);
+
+        // Light from a chunk that's loaded after spreads back the other way too
+        let mut world = World::with_chunks(IVec3::X, IVec3::X, BlockId::AIR);
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        chunk.set_block(emitter.as_uvec3().into(), block("glowstone"));
+
+        world.insert_chunk(IVec3::ZERO, chunk);
+        assert_lit_by(&world, emitter);
+
+    }
+
+    #[test]
+    fn breaking_or_replacing_an_emitter_removes_its_light() {
+        let mut world = World::with_chunks(IVec3::ZERO, IVec3::X, BlockId::AIR);
+        let emitter = IVec3::new(14, 8, 8);
+
+        world.set_block(emitter, block("glowstone"));
+        world.set_block(emitter, BlockId::AIR);
+        assert_no_block_light(&world);
+
+        world.set_block(emitter, block("glowstone"));
+        world.set_block(emitter, block("stone"));
+        assert_no_block_light(&world);
+
+    }
+
+    #[test]
+    fn removing_a_chunk_takes_its_light_out_of_its_neighbors() {
+        let mut world = World::with_chunks(IVec3::ZERO, IVec3::X, BlockId::AIR);
+        let removed_emitter = IVec3::new(14, 8, 8);
+        let kept_emitter = IVec3::new(28, 3, 12);
+
+        world.set_block(removed_emitter, block("glowstone"));
+        world.set_block(kept_emitter, block("glowstone"));
+        world.remove_chunk(IVec3::ZERO);
+
+        // Only the light from the emitter that's still loaded is left
+        assert_lit_by(&world, kept_emitter);
+
+    }
+
+    #[test]
+    fn removing_a_chunk_takes_away_the_sky_light_it_let_in() {
+        // A tunnel into a stone chunk from the open air next to it
+        let mut world = World::with_chunks(IVec3::ZERO, IVec3::ZERO, BlockId::AIR);
+        let mut stone = Chunk::filled(IVec3::X, block("stone"));
+        let tunnel: Vec<IVec3> = (0..4).map(|x| IVec3::new(16 + x, 8, 8)).collect();
+
+        for block_coord in tunnel.iter() {
+            stone.set_block(World::chunk_and_local_pos(*block_coord).1, BlockId::AIR);
+
+        }
+
+        world.insert_chunk(IVec3::X, stone);
+
+        for (i, block_coord) in tunnel.iter().enumerate() {
+            assert_eq!(light(&world, *block_coord).sky(), MAX_LIGHT - 1 - i as u8, "sky light at {block_coord}");
+
+        }
+
+        world.remove_chunk(IVec3::ZERO);
+
+        for block_coord in tunnel.iter() {
+            assert_eq!(light(&world, *block_coord).sky(), 0, "sky light at {block_coord}");
+
+        }
+
+    }
+
+    #[test]
+    fn blocks_in_the_way_shade_glowstone_light() {
+        let mut world = World::with_chunks(IVec3::ZERO, IVec3::ZERO, BlockId::AIR);
+        let emitter = IVec3::new(8, 8, 8);
+
+        world.set_block(emitter, block("glowstone"));
+        world.set_block(emitter + IVec3::X, block("stone"));
+
+        // The light has to go around the stone to reach the block behind it
+        assert_eq!(light(&world, emitter + IVec3::X).block(), 0);
+        assert_eq!(light(&world, emitter + IVec3::X * 2).block(), MAX_LIGHT - 4);
+
+        // Taking the stone away lets it straight through again
+        world.set_block(emitter + IVec3::X, BlockId::AIR);
+        assert_lit_by(&world, emitter);
+
+    }
+
+    #[test]
+    fn light_is_the_same_whatever_order_chunks_are_added_in() {
+        let generator = HeightmapGenerator::new(3);
+        let mut chunks = Vec::new();
+
+        // The chunks around the surface
+        for z in -1..=1 {
+            for y in -1..=1 {
+                for x in -1..=1 {
+                    let chunk_coord = IVec3::new(x, y, z);
+                    let sky_heights = generator.sky_heights(World::chunk_column(chunk_coord));
+                    chunks.push((chunk_coord, generator.generate(chunk_coord), sky_heights));
+
+                }
+
+            }
+
+        }
+
+        // Each chunk is lit by itself first, the way the job system does
+        let add = |world: &mut World, (chunk_coord, chunk, sky_heights): &(IVec3, Chunk, SkyHeights)| {
+            let mut chunk = chunk.clone_blocks();
+            light_chunk(*chunk_coord, &mut chunk, sky_heights);
+            world.insert_lit_chunk(*chunk_coord, chunk, sky_heights);
+
+        };
+
+        let mut forwards = World::new();
+        let mut backwards = World::new();
+        chunks.iter().for_each(|chunk| add(&mut forwards, chunk));
+        chunks.iter().rev().for_each(|chunk| add(&mut backwards, chunk));
+
+        for block_coord in block_coords(&forwards) {
+            assert_eq!(light(&forwards, block_coord), light(&backwards, block_coord), "light at {block_coord}");
+
+        }
+
+    }
+
+}
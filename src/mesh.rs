@@ -0,0 +1,504 @@
+use glam::{IVec3, Vec3};
+
+use crate::block::{BlockId, Chunk, Face, LocalPos, Vertex, BLOCK_SIZE};
+use crate::lighting::{ChunkLight, LightLevel};
+use crate::registry::{BlockRegistry, RenderMode};
+
+const VERTICES_PER_BLOCK: usize = 24;
+const INDICES_PER_BLOCK: usize = 36;
+
+/// The ambient occlusion of a vertex with no opaque blocks around it. A vertex in a corner between three opaque blocks has 0
+pub const UNOCCLUDED: u8 = 3;
+
+/// The geometry of a single chunk
+#[derive(Clone, Debug, Default)]
+pub struct ChunkMesh {
+    pub vertices: Vec<Vertex>,
+    /// The triangles of opaque and cutout faces
+    pub indices: Vec<u32>,
+    /// The triangles of translucent faces, which share the same vertices but are drawn separately, after everything else.
+    /// Each face is 6 indices long, so they can be sorted
+    pub translucent_indices: Vec<u32>,
+
+}
+
+/// How big a mesh is, for comparing meshers
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MeshStats {
+    pub vertices: usize,
+    pub indices: usize,
+
+}
+
+impl ChunkMesh {
+    pub fn stats(&self) -> MeshStats {
+        MeshStats {
+            vertices: self.vertices.len(),
+            indices: self.indices.len() + self.translucent_indices.len(),
+
+        }
+
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty() && self.translucent_indices.is_empty()
+
+    }
+
+    /// Adds a quad to the mesh, with the vertices in counter clockwise order when looking at its front
+    fn push_quad(&mut self, vertices: [Vertex; 4], ambient_occlusion: [u8; 4], translucent: bool) {
+        let start_index = self.vertices.len() as u32;
+        let indices = quad_indices(start_index, ambient_occlusion);
+
+        self.vertices.extend_from_slice(&vertices);
+
+        match translucent {
+            true => self.translucent_indices.extend_from_slice(&indices),
+            false => self.indices.extend_from_slice(&indices),
+
+        };
+
+    }
+
+    /// The translucent indices with their faces sorted from farthest to nearest to the camera, so they blend correctly
+    pub fn sorted_translucent_indices(&self, camera_pos: Vec3) -> Vec<u32> {
+        let mut faces: Vec<(f32, &[u32])> = self.translucent_indices.chunks_exact(6)
+            .map(|face| {
+                // Both triangles share a diagonal, so this works out to the center of the face
+                let center = face.iter().fold(Vec3::ZERO, |sum, index| sum + self.vertices[*index as usize].position()) / 6.0;
+
+                (center.distance_squared(camera_pos), face)
+
+            })
+            .collect();
+
+        faces.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+        faces.into_iter().flat_map(|(_, face)| face.iter().copied()).collect()
+
+    }
+
+}
+
+impl std::ops::Add for MeshStats {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self {
+            vertices: self.vertices + rhs.vertices,
+            indices: self.indices + rhs.indices,
+
+        }
+
+    }
+
+}
+
+impl std::iter::Sum for MeshStats {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |total, stats| total + stats)
+
+    }
+
+}
+
+/// The ways a chunk can be turned into a mesh
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mesher {
+    /// Emits every face of every block separately, with hidden faces left as degenerate quads
+    Naive,
+    /// Merges neighboring faces with the same texture and light into larger quads, and only emits visible faces
+    #[default]
+    Greedy,
+
+}
+
+impl Mesher {
+    /// Meshes a chunk, lighting each face with the light of the block in front of it.
+    /// With ambient occlusion, the corners of faces next to opaque blocks are darkened too
+    pub fn mesh<const N: usize>(self, chunk: &Chunk<N>, light: &ChunkLight<N>, ambient_occlusion: bool) -> ChunkMesh {
+        match self {
+            Mesher::Naive => mesh_naive(chunk, light, ambient_occlusion),
+            Mesher::Greedy => mesh_greedy(chunk, light, ambient_occlusion),
+
+        }
+
+    }
+
+}
+
+/// Whether a block's face can be seen, which is when it isn't hidden by an opaque neighbor or a neighbor of the same type.
+/// Faces on the edge of the chunk are checked against the neighboring chunk, and are visible when it isn't loaded
+fn is_face_visible<const N: usize>(chunk: &Chunk<N>, light: &ChunkLight<N>, registry: &BlockRegistry, local_pos: LocalPos, block: BlockId, face: Face) -> bool {
+    let neighbor = match local_pos.neighbor::<N>(face) {
+        Some(neighbor_pos) => chunk.get_block(neighbor_pos),
+        None => match light.block(local_pos.as_ivec3() + face.normal()) {
+            Some(neighbor) => neighbor,
+            None => return true,
+
+        },
+
+    };
+
+    neighbor != block && registry.get(neighbor).transparent
+
+}
+
+// The light shining on a block's face, which is the light of the block in front of it
+fn face_light<const N: usize>(light: &ChunkLight<N>, local_pos: LocalPos, face: Face) -> LightLevel {
+    light.get(local_pos.as_ivec3() + face.normal())
+
+}
+
+// The ambient occlusion at each corner of a block's face, from the three blocks in front of the face that touch the corner.
+// The corners are in the order (-u, -v), (+u, -v), (+u, +v), (-u, +v), with the same u and v axes as greedy meshing
+fn face_ambient_occlusion<const N: usize>(light: &ChunkLight<N>, local_pos: LocalPos, face: Face) -> [u8; 4] {
+    let axis = face.axis();
+    let mut u_dir = IVec3::ZERO;
+    let mut v_dir = IVec3::ZERO;
+    u_dir[(axis + 1) % 3] = 1;
+    v_dir[(axis + 2) % 3] = 1;
+
+    let front = local_pos.as_ivec3() + face.normal();
+
+    [(-1, -1), (1, -1), (1, 1), (-1, 1)].map(|(u, v)| {
+        let side_u = light.is_opaque(front + u_dir * u);
+        let side_v = light.is_opaque(front + v_dir * v);
+        let corner = light.is_opaque(front + u_dir * u + v_dir * v);
+
+        // With both sides opaque the corner can't be seen, whether or not there's a block in it
+        match side_u && side_v {
+            true => 0,
+            false => UNOCCLUDED - side_u as u8 - side_v as u8 - corner as u8,
+
+        }
+
+    })
+
+}
+
+// The indices of the two triangles in a quad, split along whichever diagonal has the least occluded corners.
+// Splitting along the same diagonal every time makes occlusion look different depending on which way the face is turned
+fn quad_indices(start_index: u32, ambient_occlusion: [u8; 4]) -> [u32; 6] {
+    match ambient_occlusion[0] + ambient_occlusion[2] >= ambient_occlusion[1] + ambient_occlusion[3] {
+        true => [start_index, start_index + 1, start_index + 2, start_index + 2, start_index + 3, start_index],
+        false => [start_index + 1, start_index + 2, start_index + 3, start_index + 3, start_index, start_index + 1],
+
+    }
+
+}
+
+pub fn mesh_naive<const N: usize>(chunk: &Chunk<N>, light: &ChunkLight<N>, ambient_occlusion: bool) -> ChunkMesh {
+    let registry = BlockRegistry::global();
+    let mut mesh = ChunkMesh {
+        vertices: Vec::with_capacity(Chunk::<N>::SIZE * VERTICES_PER_BLOCK),
+        indices: Vec::with_capacity(Chunk::<N>::SIZE * INDICES_PER_BLOCK),
+        translucent_indices: Vec::new(),
+
+    };
+
+    for local_pos in LocalPos::all::<N>() {
+        let block = chunk.get_block(local_pos);
+        let translucent = registry.get(block).render_mode == RenderMode::Translucent;
+        let block_num = (mesh.vertices.len() / VERTICES_PER_BLOCK) as u32;
+        let mut current_block_vertices = [Vertex::zero(); VERTICES_PER_BLOCK];
+        let mut current_block_indices = BlockId::as_indices(block_num);
+
+        if block != BlockId::AIR {
+            let block_world_pos = chunk.world_pos() + local_pos.rel_pos();
+
+            for (face_num, face) in Face::ALL.into_iter().enumerate() {
+                if is_face_visible(chunk, light, registry, local_pos, block, face) {
+                    let mut vertices = block.as_vertices(face, block_world_pos, face_light(light, local_pos, face));
+                    let mut vertex_occlusion = [UNOCCLUDED; 4];
+
+                    if ambient_occlusion {
+                        let corner_occlusion = face_ambient_occlusion(light, local_pos, face);
+                        let axis = face.axis();
+
+                        // Work out which corner of the face each vertex is at from which side of the block it's on
+                        for (vertex, occlusion) in vertices.iter_mut().zip(vertex_occlusion.iter_mut()) {
+                            let offset = vertex.position() - block_world_pos;
+                            let corner = match (offset[(axis + 1) % 3] > 0.0, offset[(axis + 2) % 3] > 0.0) {
+                                (false, false) => 0,
+                                (true, false) => 1,
+                                (true, true) => 2,
+                                (false, true) => 3,
+
+                            };
+
+                            *occlusion = corner_occlusion[corner];
+                            *vertex = vertex.with_ambient_occlusion(*occlusion);
+
+                        }
+
+                    }
+
+                    current_block_vertices[face_num * 4..(face_num + 1) * 4].copy_from_slice(&vertices);
+                    current_block_indices[face_num * 6..(face_num + 1) * 6].copy_from_slice(&quad_indices(block_num * VERTICES_PER_BLOCK as u32 + face_num as u32 * 4, vertex_occlusion));
+
+                }
+
+            }
+
+        }
+
+        mesh.vertices.extend_from_slice(&current_block_vertices);
+
+        match translucent {
+            true => mesh.translucent_indices.extend_from_slice(&current_block_indices),
+            false => mesh.indices.extend_from_slice(&current_block_indices),
+
+        };
+
+    }
+
+    mesh
+
+}
+
+// A visible face in greedy meshing. Neighboring faces are only merged when they're the same
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct GreedyFace {
+    texture: u32,
+    light: LightLevel,
+    occlusion: [u8; 4],
+    translucent: bool,
+
+}
+
+pub fn mesh_greedy<const N: usize>(chunk: &Chunk<N>, light: &ChunkLight<N>, ambient_occlusion: bool) -> ChunkMesh {
+    let registry = BlockRegistry::global();
+    let mut mesh = ChunkMesh::default();
+
+    // Every visible face in the current layer, indexed by u + v * N
+    let mut mask: Vec<Option<GreedyFace>> = vec![None; N * N];
+
+    for face in Face::ALL {
+        let axis = face.axis();
+        // The two axes along the face, in the order that makes u cross v point along the positive axis
+        let u_axis = (axis + 1) % 3;
+        let v_axis = (axis + 2) % 3;
+
+        for layer in 0..N {
+            for v in 0..N {
+                for u in 0..N {
+                    let mut pos = [0; 3];
+                    pos[axis] = layer as u32;
+                    pos[u_axis] = u as u32;
+                    pos[v_axis] = v as u32;
+
+                    let local_pos = LocalPos::new(pos[0], pos[1], pos[2]);
+                    let block = chunk.get_block(local_pos);
+
+                    mask[u + v * N] = match block != BlockId::AIR && is_face_visible(chunk, light, registry, local_pos, block, face) {
+                        true => {
+                            let occlusion = match ambient_occlusion {
+                                true => face_ambient_occlusion(light, local_pos, face),
+                                false => [UNOCCLUDED; 4],
+
+                            };
+
+                            let properties = registry.get(block);
+
+                            Some(GreedyFace {
+                                texture: properties.texture_index(face),
+                                light: face_light(light, local_pos, face),
+                                occlusion,
+                                translucent: properties.render_mode == RenderMode::Translucent,
+
+                            })
+
+                        },
+                        false => None,
+
+                    };
+
+                }
+
+            }
+
+            for v in 0..N {
+                let mut u = 0;
+
+                while u < N {
+                    let greedy_face = match mask[u + v * N] {
+                        Some(greedy_face) => greedy_face,
+                        None => {
+                            u += 1;
+                            continue;
+
+                        },
+
+                    };
+
+                    // Grow the quad along u as far as the face stays the same, then along v as long as every face in the next row matches
+                    let mut width = 1;
+
+                    while u + width < N && mask[u + width + v * N] == Some(greedy_face) {
+                        width += 1;
+
+                    }
+
+                    let mut height = 1;
+
+                    while v + height < N && (u..u + width).all(|quad_u| mask[quad_u + (v + height) * N] == Some(greedy_face)) {
+                        height += 1;
+
+                    }
+
+                    for quad_v in v..v + height {
+                        mask[u + quad_v * N..u + width + quad_v * N].fill(None);
+
+                    }
+
+                    let (vertices, occlusion) = greedy_quad_vertices(chunk.world_pos(), face, [layer, u, v, width, height], greedy_face);
+                    mesh.push_quad(vertices, occlusion, greedy_face.translucent);
+
+                    u += width;
+
+                }
+
+            }
+
+        }
+
+    }
+
+    mesh
+
+}
+
+// The vertices of a quad covering width by height faces, starting at (u, v) in the given layer, along with the occlusion of each vertex
+fn greedy_quad_vertices(chunk_pos: Vec3, face: Face, [layer, u, v, width, height]: [usize; 5], greedy_face: GreedyFace) -> ([Vertex; 4], [u8; 4]) {
+    let GreedyFace { texture, light, occlusion, .. } = greedy_face;
+    let axis = face.axis();
+    let u_axis = (axis + 1) % 3;
+    let v_axis = (axis + 2) % 3;
+
+    // Faces pointing along the positive axis are on the far side of their block
+    let plane = (layer + face.is_positive() as usize) as f32;
+
+    let corner = |corner_u: usize, corner_v: usize, occlusion: u8| {
+        // The corner's position in blocks from the chunk's first block's minimum corner
+        let mut corner = Vec3::ZERO;
+        corner[axis] = plane;
+        corner[u_axis] = corner_u as f32;
+        corner[v_axis] = corner_v as f32;
+
+        let position = chunk_pos + corner * BLOCK_SIZE - Vec3::splat(BLOCK_SIZE / 2.0);
+
+        Vertex::new(position, face_tex_coords(face, corner), texture, face.normal().as_vec3(), light, occlusion)
+
+    };
+
+    let corners = [
+        corner(u, v, occlusion[0]),
+        corner(u + width, v, occlusion[1]),
+        corner(u + width, v + height, occlusion[2]),
+        corner(u, v + height, occlusion[3]),
+    ];
+
+    // Reverse the winding of faces pointing along the negative axis so they still face outwards
+    match face.is_positive() {
+        true => (corners, occlusion),
+        false => ([corners[0], corners[3], corners[2], corners[1]], [occlusion[0], occlusion[3], occlusion[2], occlusion[1]]),
+
+    }
+
+}
+
+// Textures are laid out so they go left to right when looking at the face from outside, with sides upright
+fn face_tex_coords(face: Face, corner: Vec3) -> [f32; 2] {
+    match face {
+        Face::ZPos => [corner.x, -corner.y],
+        Face::ZNeg => [-corner.x, -corner.y],
+        Face::XPos => [-corner.z, -corner.y],
+        Face::XNeg => [corner.z, -corner.y],
+        Face::YPos => [corner.z, -corner.x],
+        Face::YNeg => [corner.z, corner.x],
+
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+
+    use super::*;
+    use crate::block::CHUNK_SIZE_AXIS;
+    use crate::world::World;
+
+    const CHUNK_FACES: usize = CHUNK_SIZE_AXIS * CHUNK_SIZE_AXIS;
+
+    fn block(name: &str) -> BlockId {
+        BlockRegistry::global().id(name).unwrap()
+
+    }
+
+    // The naive mesher leaves hidden faces in the mesh with every vertex at the origin
+    fn visible_quads(mesh: &ChunkMesh) -> usize {
+        mesh.vertices.chunks_exact(4).filter(|quad| quad.iter().any(|vertex| vertex.position() != quad[0].position())).count()
+
+    }
+
+    fn mesh_in_world(world: &World, chunk_coord: IVec3, mesher: Mesher) -> ChunkMesh {
+        let light = ChunkLight::from_world(world, chunk_coord).unwrap();
+        mesher.mesh(world.chunk(chunk_coord).unwrap(), &light, true)
+
+    }
+
+    #[test]
+    fn faces_between_solid_chunks_are_hidden() {
+        let world = World::with_chunks(IVec3::ZERO, IVec3::new(1, 0, 0), block("stone"));
+
+        for mesher in [Mesher::Naive, Mesher::Greedy] {
+            // Only the five sides facing unloaded chunks are left, as one quad each when greedy meshed
+            let mesh = mesh_in_world(&world, IVec3::ZERO, mesher);
+            let faces = visible_quads(&mesh);
+            let expected = match mesher {
+                Mesher::Naive => 5 * CHUNK_FACES,
+                Mesher::Greedy => 5,
+
+            };
+
+            assert_eq!(faces, expected, "{mesher:?}");
+
+        }
+
+    }
+
+    #[test]
+    fn translucent_blocks_have_no_seam_between_chunks() {
+        let water = block("water");
+        let world = World::with_chunks(IVec3::ZERO, IVec3::new(1, 0, 0), water);
+
+        let mesh = mesh_in_world(&world, IVec3::ZERO, Mesher::Greedy);
+        let right_edge = (CHUNK_SIZE_AXIS as f32 - 0.5) * BLOCK_SIZE;
+
+        assert_eq!(visible_quads(&mesh), 5);
+        assert!(mesh.vertices.iter().any(|vertex| vertex.position().x == right_edge));
+        assert!(mesh.vertices.chunks_exact(4).all(|quad| !quad.iter().all(|vertex| vertex.position().x == right_edge)));
+
+    }
+
+    #[test]
+    fn faces_next_to_unloaded_chunks_are_visible() {
+        let world = World::with_chunks(IVec3::ZERO, IVec3::ZERO, block("stone"));
+
+        assert_eq!(visible_quads(&mesh_in_world(&world, IVec3::ZERO, Mesher::Greedy)), 6);
+
+    }
+
+    #[test]
+    fn faces_next_to_transparent_neighbors_are_visible() {
+        let mut world = World::with_chunks(IVec3::ZERO, IVec3::new(1, 0, 0), block("stone"));
+        world.set_block(IVec3::new(CHUNK_SIZE_AXIS as i32, 0, 0), block("glass"));
+
+        // The glass block uncovers one face on the side between the chunks
+        assert_eq!(visible_quads(&mesh_in_world(&world, IVec3::ZERO, Mesher::Greedy)), 5 + 1);
+
+    }
+
+}
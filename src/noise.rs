@@ -0,0 +1,123 @@
+/// Mixes a 64 bit value into a well distributed hash (splitmix64).
+/// This is used instead of a random number crate so worlds stay the same between dependency versions
+pub fn hash_u64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+
+    x ^ (x >> 31)
+
+}
+
+/// Hashes a seed together with a list of integers, such as a block coordinate
+pub fn hash_values(seed: u64, values: &[i64]) -> u64 {
+    values.iter().fold(hash_u64(seed), |hash, value| hash_u64(hash ^ *value as u64))
+
+}
+
+/// Seeded Perlin noise in 2 and 3 dimensions, returning values roughly between -1 and 1
+#[derive(Clone)]
+pub struct Perlin {
+    // A shuffled list of 0..256, repeated twice so lookups don't need wrapping
+    permutation: Box<[u8; 512]>,
+
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut table: [u8; 256] = std::array::from_fn(|i| i as u8);
+        let mut state = seed;
+
+        // Fisher-Yates shuffle
+        for i in (1..table.len()).rev() {
+            state = hash_u64(state);
+            let j = (state % (i as u64 + 1)) as usize;
+            table.swap(i, j);
+
+        }
+
+        let permutation = Box::new(std::array::from_fn(|i| table[i % 256]));
+
+        Self { permutation }
+
+    }
+
+    fn hash(&self, x: i32, y: i32, z: i32) -> u8 {
+        let p = &self.permutation;
+        let x = (x & 255) as usize;
+        let y = (y & 255) as usize;
+        let z = (z & 255) as usize;
+
+        p[p[p[x] as usize + y] as usize + z]
+
+    }
+
+    pub fn noise2(&self, x: f64, y: f64) -> f64 {
+        self.noise3(x, y, 0.0)
+
+    }
+
+    pub fn noise3(&self, x: f64, y: f64, z: f64) -> f64 {
+        let (xi, yi, zi) = (x.floor() as i32, y.floor() as i32, z.floor() as i32);
+        let (xf, yf, zf) = (x - x.floor(), y - y.floor(), z - z.floor());
+        let (u, v, w) = (fade(xf), fade(yf), fade(zf));
+
+        let corner = |dx: i32, dy: i32, dz: i32| {
+            grad(self.hash(xi + dx, yi + dy, zi + dz), xf - dx as f64, yf - dy as f64, zf - dz as f64)
+
+        };
+
+        lerp(w,
+            lerp(v,
+                lerp(u, corner(0, 0, 0), corner(1, 0, 0)),
+                lerp(u, corner(0, 1, 0), corner(1, 1, 0)),
+            ),
+            lerp(v,
+                lerp(u, corner(0, 0, 1), corner(1, 0, 1)),
+                lerp(u, corner(0, 1, 1), corner(1, 1, 1)),
+            ),
+        )
+
+    }
+
+    /// Adds together octaves of noise, each at double the frequency and half the amplitude of the last.
+    /// The result is scaled back to roughly -1 to 1
+    pub fn fbm2(&self, x: f64, y: f64, octaves: u32) -> f64 {
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        let mut max_amplitude = 0.0;
+
+        for _ in 0..octaves {
+            total += self.noise2(x * frequency, y * frequency) * amplitude;
+            max_amplitude += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+
+        }
+
+        total / max_amplitude
+
+    }
+
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+
+}
+
+// Picks one of 12 gradient directions and dots it with the offset from the corner
+fn grad(hash: u8, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 { y } else if h == 12 || h == 14 { x } else { z };
+
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+
+}
@@ -0,0 +1,117 @@
+use std::ops::RangeInclusive;
+
+use glam::{IVec3, Vec3};
+
+use crate::block::{BlockId, Chunk, LocalPos, CHUNK_SIZE_AXIS};
+use crate::noise::hash_values;
+
+/// Where and how often an ore appears underground
+#[derive(Clone, Debug)]
+pub struct OreDistribution {
+    pub block: BlockId,
+    /// The block heights the centers of veins can be at
+    pub heights: RangeInclusive<i32>,
+    /// The average number of veins in each chunk sized area within the height range
+    pub veins_per_chunk: f32,
+    /// The radius of each vein in blocks
+    pub vein_radius: f32,
+
+}
+
+// A blob of ore around a point
+struct Vein {
+    center: Vec3,
+    radius: f32,
+
+}
+
+// Turns a hash into a number from 0 to 1
+fn unit_f32(hash: u64) -> f32 {
+    (hash >> 40) as f32 / (1u64 << 24) as f32
+
+}
+
+impl OreDistribution {
+    // The veins whose centers are in the given chunk sized cell. Veins are decided per cell rather than per chunk being
+    // generated, so a vein near a chunk border comes out the same from either side of it
+    fn veins_in_cell(&self, seed: u64, ore_index: usize, cell: IVec3) -> Vec<Vein> {
+        let chunk_size = CHUNK_SIZE_AXIS as i32;
+        let first_block = cell * chunk_size;
+
+        // Skip cells that the height range doesn't reach
+        if first_block.y > *self.heights.end() || first_block.y + chunk_size <= *self.heights.start() {
+            return Vec::new();
+
+        }
+
+        let hash = |values: &[i64]| hash_values(seed, &[&[ore_index as i64, cell.x as i64, cell.y as i64, cell.z as i64], values].concat());
+
+        // The fractional part of veins_per_chunk is the chance of one more vein
+        let extra_vein = unit_f32(hash(&[-1])) < self.veins_per_chunk.fract();
+        let vein_count = self.veins_per_chunk as i64 + extra_vein as i64;
+
+        (0..vein_count).filter_map(|i| {
+            let offset = Vec3::new(unit_f32(hash(&[i, 0])), unit_f32(hash(&[i, 1])), unit_f32(hash(&[i, 2]))) * chunk_size as f32;
+            let center = first_block.as_vec3() + offset;
+
+            self.heights.contains(&(center.y.floor() as i32)).then(|| Vein {
+                center,
+                radius: self.vein_radius * (0.75 + unit_f32(hash(&[i, 3])) * 0.5),
+
+            })
+
+        }).collect()
+
+    }
+
+}
+
+/// Replaces the host blocks in a chunk that are inside ore veins, using the world seed to place the veins
+pub fn place_ores(chunk: &mut Chunk, chunk_coord: IVec3, seed: u64, ores: &[OreDistribution], host: BlockId) {
+    let chunk_size = CHUNK_SIZE_AXIS as i32;
+    let first_block = chunk_coord * chunk_size;
+
+    for (ore_index, ore) in ores.iter().enumerate() {
+        // Veins are smaller than a chunk, so only the neighboring cells can have veins reaching into this one
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    for vein in ore.veins_in_cell(seed, ore_index, chunk_coord + IVec3::new(dx, dy, dz)) {
+                        // The part of the vein's bounding box that's in this chunk
+                        let min = ((vein.center - Vec3::splat(vein.radius)).floor().as_ivec3() - first_block).max(IVec3::ZERO);
+                        let max = ((vein.center + Vec3::splat(vein.radius)).ceil().as_ivec3() - first_block).min(IVec3::splat(chunk_size - 1));
+
+                        for z in min.z..=max.z {
+                            for y in min.y..=max.y {
+                                for x in min.x..=max.x {
+                                    let block_coord = first_block + IVec3::new(x, y, z);
+
+                                    if block_coord.as_vec3().distance(vein.center) > vein.radius {
+                                        continue;
+
+                                    }
+
+                                    let local_pos = LocalPos::new(x as u32, y as u32, z as u32);
+
+                                    if chunk.get_block(local_pos) == host {
+                                        chunk.set_block(local_pos, ore.block);
+
+                                    }
+
+                                }
+
+                            }
+
+                        }
+
+                    }
+
+                }
+
+            }
+
+        }
+
+    }
+
+}
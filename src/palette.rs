@@ -0,0 +1,340 @@
+use crate::block::BlockId;
+
+/// A fixed number of blocks stored as indices into a palette of the distinct blocks, packed into as few bits as the palette needs.
+/// When every block is the same, such as a chunk of air, only the palette is stored
+#[derive(Clone, Debug)]
+pub struct PalettedBlocks {
+    len: usize,
+    palette: Vec<BlockId>,
+    // How many bits each index takes up, which is 0 when the palette only has one block
+    bits_per_index: u32,
+    // Indices never cross from one word into the next, so some bits at the end of each word may go unused
+    data: Vec<u64>,
+
+}
+
+impl PalettedBlocks {
+    /// Creates len blocks that are all the given block
+    pub fn filled(len: usize, block: BlockId) -> Self {
+        Self {
+            len,
+            palette: vec![block],
+            bits_per_index: 0,
+            data: Vec::new(),
+
+        }
+
+    }
+
+    pub fn from_blocks(blocks: impl ExactSizeIterator<Item = BlockId>) -> Self {
+        let len = blocks.len();
+        let mut blocks = blocks.peekable();
+        let first = blocks.peek().copied().unwrap_or_default();
+        let mut paletted = Self::filled(len, first);
+
+        for (index, block) in blocks.enumerate() {
+            paletted.set(index, block);
+
+        }
+
+        paletted
+
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+
+    }
+
+    /// The distinct blocks, though blocks that have since been replaced may still be in it
+    pub fn palette(&self) -> &[BlockId] {
+        &self.palette
+
+    }
+
+    // Which word an index is in and how far it's shifted
+    fn location(&self, index: usize) -> (usize, u32) {
+        let per_word = (64 / self.bits_per_index) as usize;
+
+        (index / per_word, (index % per_word) as u32 * self.bits_per_index)
+
+    }
+
+    fn palette_index(&self, index: usize) -> usize {
+        if self.bits_per_index == 0 {
+            return 0;
+
+        }
+
+        let (word, shift) = self.location(index);
+        let mask = (1 << self.bits_per_index) - 1;
+
+        ((self.data[word] >> shift) & mask) as usize
+
+    }
+
+    fn set_palette_index(&mut self, index: usize, palette_index: usize) {
+        let (word, shift) = self.location(index);
+        let mask = ((1 << self.bits_per_index) - 1) << shift;
+
+        self.data[word] = (self.data[word] & !mask) | ((palette_index as u64) << shift);
+
+    }
+
+    pub fn get(&self, index: usize) -> BlockId {
+        assert!(index < self.len, "Block index {index} is out of bounds for {} blocks", self.len);
+
+        self.palette[self.palette_index(index)]
+
+    }
+
+    /// Sets a block, returning the block that was there before
+    pub fn set(&mut self, index: usize, block: BlockId) -> BlockId {
+        let old_block = self.get(index);
+
+        if old_block == block {
+            return old_block;
+
+        }
+
+        let palette_index = match self.palette.iter().position(|palette_block| *palette_block == block) {
+            Some(palette_index) => palette_index,
+            None => {
+                // Make room for the new block, by dropping blocks that aren't used anymore or by using more bits for each index
+                if self.palette.len() >= 1 << self.bits_per_index {
+                    self.repack(1);
+
+                }
+
+                self.palette.push(block);
+                self.palette.len() - 1
+
+            },
+
+        };
+
+        self.set_palette_index(index, palette_index);
+
+        old_block
+
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = BlockId> + '_ {
+        (0..self.len).map(|index| self.palette[self.palette_index(index)])
+
+    }
+
+    // Rebuilds the palette from the blocks that are used, with enough bits per index for them plus extra_entries more
+    fn repack(&mut self, extra_entries: usize) {
+        let mut palette: Vec<BlockId> = Vec::new();
+        let mut indices = Vec::with_capacity(self.len);
+
+        for block in self.iter() {
+            let palette_index = match palette.iter().position(|palette_block| *palette_block == block) {
+                Some(palette_index) => palette_index,
+                None => {
+                    palette.push(block);
+                    palette.len() - 1
+
+                },
+
+            };
+
+            indices.push(palette_index);
+
+        }
+
+        let palette_len = palette.len() + extra_entries;
+        let bits_per_index = match palette_len {
+            0 | 1 => 0,
+            len => usize::BITS - (len - 1).leading_zeros(),
+
+        };
+
+        self.palette = palette;
+        self.bits_per_index = bits_per_index;
+        self.data = Vec::new();
+
+        if let Some(per_word) = 64_u32.checked_div(bits_per_index) {
+            self.data = vec![0; self.len.div_ceil(per_word as usize)];
+
+            for (index, palette_index) in indices.into_iter().enumerate() {
+                self.set_palette_index(index, palette_index);
+
+            }
+
+        }
+
+    }
+
+    /// Roughly how many bytes the blocks take up in memory, not counting the struct itself
+    pub fn memory_usage(&self) -> usize {
+        self.palette.capacity() * std::mem::size_of::<BlockId>() + self.data.capacity() * std::mem::size_of::<u64>()
+
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // As many blocks as a chunk has
+    const LEN: usize = 4096;
+
+    // A pattern that uses every block in the palette and changes from each block to the next
+    fn pattern(distinct: u16) -> impl Fn(usize) -> BlockId {
+        move |index| BlockId((index * 7 % distinct as usize) as u16)
+
+    }
+
+    fn assert_matches(blocks: &PalettedBlocks, expected: impl Fn(usize) -> BlockId) {
+        for index in 0..blocks.len() {
+            assert_eq!(blocks.get(index), expected(index), "block {index}");
+
+        }
+
+        assert!(blocks.iter().enumerate().all(|(index, block)| block == expected(index)));
+
+    }
+
+    #[test]
+    fn blocks_round_trip_as_the_palette_grows() {
+        let mut blocks = PalettedBlocks::filled(LEN, BlockId(0));
+
+        // Each step needs another bit per index, from 0 bits for a single block up to 4 bits for 16
+        for (distinct, bits) in [(1, 0), (2, 1), (4, 2), (8, 3), (16, 4), (17, 5)] {
+            let expected = pattern(distinct);
+
+            for index in 0..LEN {
+                blocks.set(index, expected(index));
+
+            }
+
+            assert_eq!(blocks.bits_per_index, bits, "{distinct} blocks");
+            assert_matches(&blocks, &expected);
+
+        }
+
+    }
+
+    #[test]
+    fn set_returns_the_old_block() {
+        let mut blocks = PalettedBlocks::filled(LEN, BlockId(3));
+
+        assert_eq!(blocks.set(10, BlockId(5)), BlockId(3));
+        assert_eq!(blocks.set(10, BlockId(6)), BlockId(5));
+        assert_eq!(blocks.set(10, BlockId(6)), BlockId(6));
+        assert_eq!(blocks.get(11), BlockId(3));
+
+    }
+
+    #[test]
+    fn indices_next_to_word_boundaries_do_not_overwrite_each_other() {
+        // 3 and 5 bits don't divide 64, so these leave bits unused at the end of each word
+        for distinct in [5, 17, 300] {
+            let mut blocks = PalettedBlocks::from_blocks((0..LEN).map(pattern(distinct)));
+            let per_word = (64 / blocks.bits_per_index) as usize;
+
+            // The last index in a word and the first in the next, set to the highest palette index so every bit is set
+            for word in 0..4 {
+                let last = word * per_word + per_word - 1;
+                let highest = BlockId(distinct - 1);
+
+                blocks.set(last, highest);
+                blocks.set(last + 1, highest);
+
+                assert_eq!(blocks.get(last - 1), pattern(distinct)(last - 1), "{distinct} blocks, word {word}");
+                assert_eq!(blocks.get(last), highest);
+                assert_eq!(blocks.get(last + 1), highest);
+                assert_eq!(blocks.get(last + 2), pattern(distinct)(last + 2), "{distinct} blocks, word {word}");
+
+            }
+
+        }
+
+    }
+
+    #[test]
+    fn the_last_block_fits_in_the_last_word() {
+        let mut blocks = PalettedBlocks::from_blocks((0..LEN).map(pattern(5)));
+
+        blocks.set(LEN - 1, BlockId(4));
+        assert_eq!(blocks.get(LEN - 1), BlockId(4));
+
+    }
+
+    #[test]
+    fn a_single_block_only_stores_the_palette() {
+        let blocks = PalettedBlocks::filled(LEN, BlockId(9));
+
+        assert_eq!(blocks.bits_per_index, 0);
+        assert!(blocks.data.is_empty());
+        assert_eq!(blocks.palette(), [BlockId(9)]);
+        assert_matches(&blocks, |_| BlockId(9));
+
+        // Building from blocks that are all the same takes the same shortcut
+        let from_blocks = PalettedBlocks::from_blocks(std::iter::repeat_n(BlockId(9), LEN));
+        assert!(from_blocks.data.is_empty());
+        assert_eq!(from_blocks.memory_usage(), blocks.memory_usage());
+
+    }
+
+    #[test]
+    fn setting_the_same_block_keeps_the_shortcut() {
+        let mut blocks = PalettedBlocks::filled(LEN, BlockId(9));
+
+        for index in 0..LEN {
+            blocks.set(index, BlockId(9));
+
+        }
+
+        assert!(blocks.data.is_empty());
+
+    }
+
+    #[test]
+    fn memory_usage_shrinks_once_blocks_stop_being_used() {
+        let mut blocks = PalettedBlocks::from_blocks((0..LEN).map(pattern(16)));
+        let full = blocks.memory_usage();
+
+        // Back to a single block, but the palette only gets rebuilt when a new block needs room
+        for index in 0..LEN {
+            blocks.set(index, BlockId(0));
+
+        }
+
+        blocks.set(0, BlockId(100));
+
+        assert_eq!(blocks.bits_per_index, 1);
+        assert!(blocks.memory_usage() < full / 2, "{} bytes, from {full}", blocks.memory_usage());
+        assert_eq!(blocks.get(0), BlockId(100));
+        assert_eq!(blocks.get(1), BlockId(0));
+
+    }
+
+    #[test]
+    fn memory_usage_stays_bounded_while_blocks_are_swapped_out() {
+        let mut blocks = PalettedBlocks::filled(LEN, BlockId(0));
+        let mut most = 0;
+
+        // Only two blocks are ever in use at once, though hundreds pass through
+        for block in 1..500 {
+            blocks.set(block as usize % LEN, BlockId(block));
+            blocks.set((block as usize - 1) % LEN, BlockId(0));
+
+            most = most.max(blocks.memory_usage());
+
+        }
+
+        assert!(blocks.bits_per_index <= 2);
+        assert!(most <= PalettedBlocks::from_blocks((0..LEN).map(pattern(4))).memory_usage() + 64, "{most} bytes");
+
+    }
+
+}
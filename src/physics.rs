@@ -0,0 +1,489 @@
+use glam::{IVec3, Vec3};
+
+use crate::block::{BlockId, BLOCK_SIZE};
+use crate::settings::Settings;
+use crate::world::World;
+
+/// How wide the player is, in world units
+pub const PLAYER_WIDTH: f32 = 0.6 * BLOCK_SIZE;
+/// How tall the player is, in world units
+pub const PLAYER_HEIGHT: f32 = 1.8 * BLOCK_SIZE;
+/// How far above the player's feet their eyes are, in world units
+pub const PLAYER_EYE_HEIGHT: f32 = 1.62 * BLOCK_SIZE;
+/// The highest ledge the player walks up without jumping, in world units
+pub const STEP_HEIGHT: f32 = BLOCK_SIZE;
+// The fastest the player can fall, in world units per second
+const MAX_FALL_SPEED: f32 = 120.0;
+
+/// An axis aligned bounding box
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+
+    }
+
+    /// The space taken up by the block at a block coordinate
+    pub fn block(block_coord: IVec3) -> Self {
+        let center = block_coord.as_vec3() * BLOCK_SIZE;
+        let half_size = Vec3::splat(BLOCK_SIZE / 2.0);
+
+        Self::new(center - half_size, center + half_size)
+
+    }
+
+    pub fn translate(self, offset: Vec3) -> Self {
+        Self::new(self.min + offset, self.max + offset)
+
+    }
+
+    /// Grows the box in the direction of offset, so it covers everywhere the box passes through when moved by it
+    pub fn expand_towards(self, offset: Vec3) -> Self {
+        Self::new(self.min + offset.min(Vec3::ZERO), self.max + offset.max(Vec3::ZERO))
+
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.cmplt(other.max).all() && self.max.cmpgt(other.min).all()
+
+    }
+
+    /// Shortens a movement of the moving box along an axis, so it stops when it touches this box.
+    /// Boxes that already overlap don't block each other, so something stuck inside a block can still get out
+    pub fn clip_movement(&self, moving: &Aabb, axis: usize, movement: f32) -> f32 {
+        let overlaps_other_axes = (0..3)
+            .filter(|other_axis| *other_axis != axis)
+            .all(|other_axis| moving.min[other_axis] < self.max[other_axis] && moving.max[other_axis] > self.min[other_axis]);
+
+        if !overlaps_other_axes {
+            return movement;
+
+        }
+
+        if movement > 0.0 && moving.max[axis] <= self.min[axis] {
+            movement.min(self.min[axis] - moving.max[axis])
+
+        } else if movement < 0.0 && moving.min[axis] >= self.max[axis] {
+            movement.max(self.max[axis] - moving.min[axis])
+
+        } else {
+            movement
+
+        }
+
+    }
+
+}
+
+/// Whether the block at a block coordinate stops the player. Chunks that haven't loaded yet count as solid,
+/// so the player can't fall out of the world while the ground is still being generated
+pub fn is_solid(world: &World, block_coord: IVec3) -> bool {
+    match world.get_block(block_coord) {
+        Some(block) => block != BlockId::AIR && block.properties().solid,
+        None => true,
+
+    }
+
+}
+
+/// Moves a box by offset, stopping it at any solid blocks in the way. Returns the offset it actually moved by
+pub fn move_and_collide(world: &World, aabb: Aabb, offset: Vec3) -> Vec3 {
+    let region = aabb.expand_towards(offset);
+    let min_block = (region.min / BLOCK_SIZE + Vec3::splat(0.5)).floor().as_ivec3();
+    let max_block = (region.max / BLOCK_SIZE + Vec3::splat(0.5)).floor().as_ivec3();
+
+    let mut blocks = Vec::new();
+
+    for z in min_block.z..=max_block.z {
+        for y in min_block.y..=max_block.y {
+            for x in min_block.x..=max_block.x {
+                let block_coord = IVec3::new(x, y, z);
+
+                if is_solid(world, block_coord) {
+                    blocks.push(Aabb::block(block_coord));
+
+                }
+
+            }
+
+        }
+
+    }
+
+    // Moving one axis at a time lets the box slide along walls instead of sticking to them.
+    // Vertical movement goes first so landing on the ground doesn't catch on the edges of blocks
+    let mut moved = Vec3::ZERO;
+    let mut aabb = aabb;
+
+    for axis in [1, 0, 2] {
+        let mut movement = offset[axis];
+
+        for block in blocks.iter() {
+            movement = block.clip_movement(&aabb, axis, movement);
+
+        }
+
+        moved[axis] = movement;
+
+        let mut axis_offset = Vec3::ZERO;
+        axis_offset[axis] = movement;
+        aabb = aabb.translate(axis_offset);
+
+    }
+
+    moved
+
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MovementMode {
+    /// Gravity pulls the player down, and they collide with blocks
+    Walk,
+    /// The player moves freely in every direction, through blocks
+    Fly,
+
+}
+
+/// What the player wants to do this step
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MovementInput {
+    /// The direction to move in, on the horizontal plane. It doesn't need to be normalized
+    pub horizontal: Vec3,
+    /// Whether to jump, or rise while flying
+    pub up: bool,
+    /// Whether to sink while flying
+    pub down: bool,
+
+}
+
+/// The player's body
+#[derive(Clone, Debug)]
+pub struct Player {
+    /// The position of the center of the player's feet
+    pub pos: Vec3,
+    pub velocity: Vec3,
+    pub mode: MovementMode,
+    on_ground: bool,
+
+}
+
+impl Player {
+    pub fn new(pos: Vec3, mode: MovementMode) -> Self {
+        Self {
+            pos,
+            velocity: Vec3::ZERO,
+            mode,
+            on_ground: false,
+
+        }
+
+    }
+
+    /// Where the player's eyes are, which is where the camera goes
+    pub fn eye_pos(&self) -> Vec3 {
+        self.pos + Vec3::Y * PLAYER_EYE_HEIGHT
+
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        let half_width = PLAYER_WIDTH / 2.0;
+
+        Aabb::new(
+            self.pos - Vec3::new(half_width, 0.0, half_width),
+            self.pos + Vec3::new(half_width, PLAYER_HEIGHT, half_width),
+        )
+
+    }
+
+    /// Whether the player was standing on something at the end of the last step
+    pub fn on_ground(&self) -> bool {
+        self.on_ground
+
+    }
+
+    pub fn toggle_mode(&mut self) {
+        self.mode = match self.mode {
+            MovementMode::Walk => MovementMode::Fly,
+            MovementMode::Fly => MovementMode::Walk,
+
+        };
+
+        self.velocity = Vec3::ZERO;
+
+    }
+
+    /// Advances the player by delta seconds
+    pub fn step(&mut self, world: &World, input: &MovementInput, delta: f32, settings: &Settings) {
+        let horizontal = input.horizontal.normalize_or_zero();
+
+        match self.mode {
+            MovementMode::Fly => {
+                let vertical = input.up as i32 - input.down as i32;
+                self.velocity = (horizontal + Vec3::Y * vertical as f32) * settings.fly_speed;
+                self.pos += self.velocity * delta;
+                self.on_ground = false;
+
+            },
+            MovementMode::Walk => {
+                self.velocity.x = horizontal.x * settings.walk_speed;
+                self.velocity.z = horizontal.z * settings.walk_speed;
+                self.velocity.y = (self.velocity.y - settings.gravity * delta).max(-MAX_FALL_SPEED);
+
+                if input.up && self.on_ground {
+                    self.velocity.y = settings.jump_speed;
+
+                }
+
+                self.walk(world, self.velocity * delta);
+
+            },
+
+        };
+
+    }
+
+    fn walk(&mut self, world: &World, offset: Vec3) {
+        let aabb = self.aabb();
+        let mut moved = move_and_collide(world, aabb, offset);
+
+        // When a wall stops the player while they're on the ground, try stepping up onto it
+        let blocked_horizontally = moved.x != offset.x || moved.z != offset.z;
+
+        if self.on_ground && blocked_horizontally {
+            let up = move_and_collide(world, aabb, Vec3::Y * STEP_HEIGHT);
+            let stepped_aabb = aabb.translate(up);
+            let across = move_and_collide(world, stepped_aabb, Vec3::new(offset.x, 0.0, offset.z));
+            let down = move_and_collide(world, stepped_aabb.translate(across), Vec3::new(0.0, offset.y.min(0.0) - up.y, 0.0));
+            let stepped = up + across + down;
+
+            // Only take the step if it gets further than walking into the wall did
+            if stepped.x * stepped.x + stepped.z * stepped.z > moved.x * moved.x + moved.z * moved.z {
+                moved = stepped;
+
+            }
+
+        }
+
+        self.pos += moved;
+
+        let hit_floor = offset.y < 0.0 && moved.y > offset.y;
+        let hit_ceiling = offset.y > 0.0 && moved.y < offset.y;
+
+        if hit_floor || hit_ceiling {
+            self.velocity.y = 0.0;
+
+        }
+
+        self.on_ground = hit_floor;
+
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::block::{Chunk, LocalPos, CHUNK_SIZE_AXIS};
+    use crate::registry::BlockRegistry;
+
+    use super::*;
+
+    const DELTA: f32 = 1.0 / 60.0;
+    // The block height of the floor in the test world, whose top is at 5 world units
+    const FLOOR_Y: u32 = 2;
+    const FLOOR_TOP: f32 = (FLOOR_Y as f32 + 0.5) * BLOCK_SIZE;
+
+    // A single chunk with a stone floor and the given blocks on it. Everything around it is unloaded, which counts as solid
+    fn world_with(blocks: &[IVec3]) -> World {
+        let stone = BlockRegistry::global().id("stone").unwrap();
+        let mut chunk = Chunk::new(IVec3::ZERO);
+
+        for local_pos in LocalPos::all::<CHUNK_SIZE_AXIS>().filter(|local_pos| local_pos.y == FLOOR_Y) {
+            chunk.set_block(local_pos, stone);
+
+        }
+
+        for block_coord in blocks {
+            chunk.set_block(block_coord.as_uvec3().into(), stone);
+
+        }
+
+        let mut world = World::new();
+        world.insert_chunk(IVec3::ZERO, chunk);
+
+        world
+
+    }
+
+    // A wall across the chunk at block x, going up height blocks from the floor
+    fn wall(x: i32, height: i32) -> Vec<IVec3> {
+        (0..CHUNK_SIZE_AXIS as i32)
+            .flat_map(|z| (1..=height).map(move |y| IVec3::new(x, FLOOR_Y as i32 + y, z)))
+            .collect()
+
+    }
+
+    // A player standing on the floor in the middle of block x
+    fn standing_player(world: &World, x: i32) -> Player {
+        let mut player = Player::new(Vec3::new(x as f32 * BLOCK_SIZE, FLOOR_TOP, 8.0 * BLOCK_SIZE), MovementMode::Walk);
+        player.step(world, &MovementInput::default(), DELTA, &Settings::default());
+
+        assert!(player.on_ground());
+
+        player
+
+    }
+
+    fn walk(player: &mut Player, world: &World, horizontal: Vec3, steps: usize) {
+        let input = MovementInput { horizontal, ..Default::default() };
+
+        for _ in 0..steps {
+            player.step(world, &input, DELTA, &Settings::default());
+
+        }
+
+    }
+
+    #[test]
+    fn boxes_stop_at_the_blocks_they_touch() {
+        let block = Aabb::block(IVec3::ZERO);
+        let above = Aabb::new(Vec3::new(-0.5, 2.0, -0.5), Vec3::new(0.5, 3.0, 0.5));
+
+        assert_eq!(block.clip_movement(&above, 1, -5.0), -1.0);
+        assert_eq!(block.clip_movement(&above, 1, 5.0), 5.0);
+        // Moving sideways past the block doesn't touch it
+        assert_eq!(block.clip_movement(&above, 0, 5.0), 5.0);
+
+        // Boxes that already overlap can move out of each other
+        let inside = Aabb::new(Vec3::splat(-0.5), Vec3::splat(0.5));
+        assert_eq!(block.clip_movement(&inside, 1, 3.0), 3.0);
+
+    }
+
+    #[test]
+    fn falling_stops_on_the_floor() {
+        let world = world_with(&[]);
+        let mut player = Player::new(Vec3::new(16.0, 20.0, 16.0), MovementMode::Walk);
+
+        for _ in 0..120 {
+            player.step(&world, &MovementInput::default(), DELTA, &Settings::default());
+
+        }
+
+        assert!(player.on_ground());
+        assert_eq!(player.pos.y, FLOOR_TOP);
+        assert_eq!(player.velocity.y, 0.0);
+
+    }
+
+    #[test]
+    fn moving_into_the_floor_is_clipped() {
+        let world = world_with(&[]);
+        let aabb = Player::new(Vec3::new(16.0, FLOOR_TOP + 1.0, 16.0), MovementMode::Walk).aabb();
+
+        assert_eq!(move_and_collide(&world, aabb, Vec3::new(0.5, -3.0, 0.0)), Vec3::new(0.5, -1.0, 0.0));
+
+    }
+
+    #[test]
+    fn walls_are_slid_along() {
+        let world = world_with(&wall(10, 3));
+        let mut player = standing_player(&world, 8);
+        let start_z = player.pos.z;
+
+        walk(&mut player, &world, Vec3::new(1.0, 0.0, 1.0), 30);
+
+        // Pressed up against the wall, but still moving along it
+        let wall_face = 9.5 * BLOCK_SIZE;
+        assert!((player.pos.x - (wall_face - PLAYER_WIDTH / 2.0)).abs() < 1e-4, "{}", player.pos.x);
+        assert!(player.pos.z > start_z + 1.0);
+        assert_eq!(player.pos.y, FLOOR_TOP);
+
+    }
+
+    #[test]
+    fn steps_up_onto_single_blocks() {
+        // A ledge that runs on to the edge of the chunk, so the player doesn't walk back off it
+        let ledge: Vec<IVec3> = (10..CHUNK_SIZE_AXIS as i32).flat_map(|x| wall(x, 1)).collect();
+        let world = world_with(&ledge);
+        let mut player = standing_player(&world, 8);
+
+        walk(&mut player, &world, Vec3::X, 60);
+
+        assert!(player.pos.x > 10.5 * BLOCK_SIZE, "{}", player.pos.x);
+        assert_eq!(player.pos.y, FLOOR_TOP + BLOCK_SIZE);
+        assert!(player.on_ground());
+
+    }
+
+    #[test]
+    fn does_not_step_up_two_blocks() {
+        let world = world_with(&wall(10, 2));
+        let mut player = standing_player(&world, 8);
+
+        walk(&mut player, &world, Vec3::X, 60);
+
+        assert!(player.pos.x < 9.5 * BLOCK_SIZE, "{}", player.pos.x);
+        assert_eq!(player.pos.y, FLOOR_TOP);
+
+    }
+
+    #[test]
+    fn only_jumps_from_the_ground() {
+        let world = world_with(&[]);
+        let settings = Settings::default();
+        let jump = MovementInput { up: true, ..Default::default() };
+
+        let mut player = standing_player(&world, 8);
+        player.step(&world, &jump, DELTA, &settings);
+
+        assert!(!player.on_ground());
+        assert!(player.pos.y > FLOOR_TOP);
+        assert_eq!(player.velocity.y, settings.jump_speed);
+
+        // Holding jump in the air doesn't jump again
+        let velocity = player.velocity.y;
+        player.step(&world, &jump, DELTA, &settings);
+        assert!(player.velocity.y < velocity);
+
+        // Falling from the top of a jump never jumps either
+        let mut falling = Player::new(Vec3::new(16.0, FLOOR_TOP + 6.0, 16.0), MovementMode::Walk);
+        falling.step(&world, &jump, DELTA, &settings);
+        assert!(falling.velocity.y < 0.0);
+
+        // The jump lands back on the floor
+        for _ in 0..120 {
+            player.step(&world, &MovementInput::default(), DELTA, &settings);
+
+        }
+
+        assert!(player.on_ground());
+        assert_eq!(player.pos.y, FLOOR_TOP);
+
+    }
+
+    #[test]
+    fn flying_ignores_gravity_and_blocks() {
+        let world = world_with(&[]);
+        let mut player = Player::new(Vec3::new(16.0, FLOOR_TOP + 2.0, 16.0), MovementMode::Fly);
+
+        player.step(&world, &MovementInput::default(), DELTA, &Settings::default());
+        assert_eq!(player.pos.y, FLOOR_TOP + 2.0);
+
+        let down = MovementInput { down: true, ..Default::default() };
+
+        for _ in 0..60 {
+            player.step(&world, &down, DELTA, &Settings::default());
+
+        }
+
+        assert!(player.pos.y < FLOOR_TOP - BLOCK_SIZE);
+
+    }
+
+}
@@ -0,0 +1,184 @@
+use glam::{IVec3, Vec3};
+
+use crate::block::{BlockId, BLOCK_SIZE};
+use crate::world::World;
+
+/// The block a ray ran into
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    pub block_coord: IVec3,
+    pub block: BlockId,
+    /// The normal of the face the ray entered the block through, or zero if the ray started inside the block
+    pub normal: IVec3,
+    /// How far along the ray the block was hit, in world units
+    pub distance: f32,
+
+}
+
+/// Steps a ray through the world one block at a time (a DDA), returning the first solid block it hits within max_distance world units
+pub fn raycast(world: &World, origin: Vec3, direction: Vec3, max_distance: f32) -> Option<RayHit> {
+    let direction = direction.try_normalize()?;
+
+    // Blocks are centered on their coordinate, so shifting by half a block puts their edges on whole numbers
+    let start = origin / BLOCK_SIZE + Vec3::splat(0.5);
+    let mut block_coord = start.floor().as_ivec3();
+
+    // signum would give 1 for 0, but the ray never steps along an axis it isn't moving on
+    let step = IVec3::from(direction.to_array().map(|d| (d > 0.0) as i32 - (d < 0.0) as i32));
+    // How far along the ray it takes to cross a whole block on each axis
+    let t_delta = (BLOCK_SIZE / direction.abs()).to_array();
+    // How far along the ray the next block edge on each axis is
+    let mut t_max = [0.0; 3];
+
+    for axis in 0..3 {
+        t_max[axis] = match step[axis] {
+            1 => (block_coord[axis] as f32 + 1.0 - start[axis]) * t_delta[axis],
+            -1 => (start[axis] - block_coord[axis] as f32) * t_delta[axis],
+            _ => f32::INFINITY,
+
+        };
+
+    }
+
+    let mut normal = IVec3::ZERO;
+    let mut distance = 0.0;
+
+    while distance <= max_distance {
+        if let Some(block) = world.get_block(block_coord) {
+            if block != BlockId::AIR && block.properties().solid {
+                return Some(RayHit { block_coord, block, normal, distance });
+
+            }
+
+        }
+
+        // Step into the next block along whichever axis has the nearest edge
+        let axis = (0..3).min_by(|a, b| t_max[*a].total_cmp(&t_max[*b])).unwrap();
+
+        distance = t_max[axis];
+        t_max[axis] += t_delta[axis];
+        block_coord[axis] += step[axis];
+
+        normal = IVec3::ZERO;
+        normal[axis] = -step[axis];
+
+    }
+
+    None
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::block::{Chunk, Face};
+    use crate::registry::BlockRegistry;
+
+    use super::*;
+
+    const ORIGIN_BLOCK: IVec3 = IVec3::new(8, 8, 8);
+
+    fn block(name: &str) -> BlockId {
+        BlockRegistry::global().id(name).unwrap()
+
+    }
+
+    // A single chunk of air with the given blocks in it
+    fn world_with(blocks: &[(IVec3, BlockId)]) -> World {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+
+        for (block_coord, block) in blocks {
+            chunk.set_block(World::chunk_and_local_pos(*block_coord).1, *block);
+
+        }
+
+        let mut world = World::new();
+        world.insert_chunk(IVec3::ZERO, chunk);
+
+        world
+
+    }
+
+    fn center(block_coord: IVec3) -> Vec3 {
+        block_coord.as_vec3() * BLOCK_SIZE
+
+    }
+
+    #[test]
+    fn hits_blocks_along_every_axis() {
+        for face in Face::ALL {
+            let target = ORIGIN_BLOCK + face.normal() * 4;
+            let world = world_with(&[(target, block("stone"))]);
+
+            let hit = raycast(&world, center(ORIGIN_BLOCK), face.normal().as_vec3(), 20.0).unwrap();
+
+            assert_eq!(hit.block_coord, target, "{face:?}");
+            assert_eq!(hit.block, block("stone"), "{face:?}");
+            // The ray comes in through the face pointing back at it
+            assert_eq!(hit.normal, face.opposite().normal(), "{face:?}");
+            // From the middle of the starting block to the near side of the target, 3.5 blocks away
+            assert!((hit.distance - 3.5 * BLOCK_SIZE).abs() < 1e-4, "{face:?} hit at {}", hit.distance);
+
+        }
+
+    }
+
+    #[test]
+    fn stops_at_max_distance() {
+        let target = ORIGIN_BLOCK + IVec3::X * 4;
+        let world = world_with(&[(target, block("stone"))]);
+        let distance = 3.5 * BLOCK_SIZE;
+
+        assert!(raycast(&world, center(ORIGIN_BLOCK), Vec3::X, distance - 0.01).is_none());
+        assert!(raycast(&world, center(ORIGIN_BLOCK), Vec3::X, distance).is_some());
+        assert!(raycast(&world, center(ORIGIN_BLOCK), Vec3::NEG_X, 20.0).is_none());
+
+    }
+
+    #[test]
+    fn starting_inside_a_block_hits_it_with_no_normal() {
+        let world = world_with(&[(ORIGIN_BLOCK, block("stone"))]);
+        let hit = raycast(&world, center(ORIGIN_BLOCK) + Vec3::splat(0.3), Vec3::new(1.0, -2.0, 0.5), 20.0).unwrap();
+
+        assert_eq!(hit.block_coord, ORIGIN_BLOCK);
+        assert_eq!(hit.normal, IVec3::ZERO);
+        assert_eq!(hit.distance, 0.0);
+
+    }
+
+    #[test]
+    fn passes_through_blocks_that_are_not_solid() {
+        let world = world_with(&[(ORIGIN_BLOCK + IVec3::Z * 2, block("water")), (ORIGIN_BLOCK + IVec3::Z * 3, block("dirt"))]);
+        let hit = raycast(&world, center(ORIGIN_BLOCK), Vec3::Z, 20.0).unwrap();
+
+        assert_eq!(hit.block_coord, ORIGIN_BLOCK + IVec3::Z * 3);
+
+    }
+
+    #[test]
+    fn finds_the_face_a_diagonal_ray_enters_through() {
+        let target = ORIGIN_BLOCK + IVec3::new(3, 1, 0);
+        let world = world_with(&[(target, block("stone"))]);
+
+        // Mostly along x and a little up, so the ray is already level with the block by the time it reaches its side
+        let hit = raycast(&world, center(ORIGIN_BLOCK) + Vec3::Y * 0.8, Vec3::new(1.0, 0.2, 0.0), 20.0).unwrap();
+
+        assert_eq!(hit.block_coord, target);
+        assert_eq!(hit.normal, IVec3::NEG_X);
+
+        // The same block from below
+        let hit = raycast(&world, center(target - IVec3::Y * 3), Vec3::Y, 20.0).unwrap();
+
+        assert_eq!(hit.block_coord, target);
+        assert_eq!(hit.normal, IVec3::NEG_Y);
+
+    }
+
+    #[test]
+    fn zero_direction_never_hits() {
+        let world = world_with(&[(ORIGIN_BLOCK, block("stone"))]);
+
+        assert!(raycast(&world, center(ORIGIN_BLOCK), Vec3::ZERO, 20.0).is_none());
+
+    }
+
+}
@@ -0,0 +1,425 @@
+use std::collections::HashMap;
+
+use anyhow::*;
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+
+use crate::block::{BlockId, Face};
+
+static GLOBAL_REGISTRY: OnceCell<BlockRegistry> = OnceCell::new();
+
+/// How a block's faces are drawn
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderMode {
+    /// Every pixel of the texture is drawn
+    #[default]
+    Opaque,
+    /// Pixels are either drawn or left out depending on their alpha, like the gaps between leaves
+    Cutout,
+    /// Pixels are blended with whatever is behind them, so they're drawn after everything else from back to front
+    Translucent,
+
+}
+
+/// The properties shared by every block of a single type
+#[derive(Clone, Debug)]
+pub struct BlockProperties {
+    pub name: String,
+    /// Whether entities collide with the block
+    pub solid: bool,
+    /// Whether the faces of blocks behind this one can be seen through it
+    pub transparent: bool,
+    pub render_mode: RenderMode,
+    /// The name of the texture used by each face, in the same order as Face::ALL
+    pub textures: [String; 6],
+    // The index of each face's texture in the registry's texture list
+    texture_indices: [u32; 6],
+    /// How long the block takes to break. Blocks with a negative hardness can't be broken
+    pub hardness: f32,
+    /// The light level the block emits, from 0 to 15
+    pub light_emission: u8,
+
+}
+
+impl BlockProperties {
+    fn air() -> Self {
+        Self {
+            name: String::from("air"),
+            solid: false,
+            transparent: true,
+            render_mode: RenderMode::Opaque,
+            textures: Default::default(),
+            texture_indices: [0; 6],
+            hardness: 0.0,
+            light_emission: 0,
+
+        }
+
+    }
+
+    pub fn texture(&self, face: Face) -> &str {
+        &self.textures[face as usize]
+
+    }
+
+    pub fn is_breakable(&self) -> bool {
+        self.hardness >= 0.0
+
+    }
+
+    /// The index of the face's texture in BlockRegistry::texture_names
+    pub fn texture_index(&self, face: Face) -> u32 {
+        self.texture_indices[face as usize]
+
+    }
+
+}
+
+// A block as it's written in the data file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BlockDefinition {
+    name: String,
+    #[serde(default = "default_true")]
+    solid: bool,
+    #[serde(default)]
+    transparent: bool,
+    #[serde(default)]
+    render_mode: RenderMode,
+    texture: Option<String>,
+    top: Option<String>,
+    bottom: Option<String>,
+    side: Option<String>,
+    #[serde(default)]
+    hardness: f32,
+    #[serde(default)]
+    light_emission: u8,
+
+}
+
+fn default_true() -> bool {
+    true
+
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegistryFile {
+    #[serde(default)]
+    block: Vec<BlockDefinition>,
+
+}
+
+impl TryFrom<BlockDefinition> for BlockProperties {
+    type Error = Error;
+
+    fn try_from(definition: BlockDefinition) -> Result<Self> {
+        let face_texture = |face_texture: &Option<String>| {
+            face_texture.as_ref()
+                .or(definition.texture.as_ref())
+                .cloned()
+                .with_context(|| format!("Block {} is missing a texture", definition.name))
+
+        };
+
+        let top = face_texture(&definition.top)?;
+        let bottom = face_texture(&definition.bottom)?;
+        let side = face_texture(&definition.side)?;
+
+        ensure!(definition.light_emission <= 15, "Block {} has a light emission above 15", definition.name);
+        // Anything behind a block that can be seen through has to be drawn too
+        ensure!(definition.transparent || definition.render_mode == RenderMode::Opaque, "Block {} can be seen through, so it needs to be transparent", definition.name);
+
+        Ok(Self {
+            solid: definition.solid,
+            transparent: definition.transparent,
+            render_mode: definition.render_mode,
+            // ZPos, ZNeg, XPos, XNeg, YPos, YNeg
+            textures: [side.clone(), side.clone(), side.clone(), side, top, bottom],
+            texture_indices: [0; 6],
+            hardness: definition.hardness,
+            light_emission: definition.light_emission,
+            name: definition.name,
+
+        })
+
+    }
+
+}
+
+/// Every type of block, indexed by BlockId
+pub struct BlockRegistry {
+    blocks: Vec<BlockProperties>,
+    ids: HashMap<String, BlockId>,
+    // Every texture used by a block, with no duplicates
+    texture_names: Vec<String>,
+
+}
+
+impl BlockRegistry {
+    /// Parses a registry from the contents of a blocks.toml file
+    pub fn from_toml_str(toml_str: &str) -> Result<Self> {
+        let file: RegistryFile = toml::from_str(toml_str)?;
+
+        let mut registry = Self {
+            blocks: Vec::with_capacity(file.block.len() + 1),
+            ids: HashMap::with_capacity(file.block.len() + 1),
+            texture_names: Vec::new(),
+
+        };
+
+        registry.register(BlockProperties::air())?;
+
+        for definition in file.block {
+            registry.register(definition.try_into()?)?;
+
+        }
+
+        Ok(registry)
+
+    }
+
+    /// Loads a registry from a blocks.toml file on disk
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let toml_str = std::fs::read_to_string(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+
+        Self::from_toml_str(&toml_str)
+
+    }
+
+    /// The registry compiled into the game from assets/blocks.toml
+    pub fn builtin() -> Self {
+        Self::from_toml_str(include_str!("../assets/blocks.toml")).expect("The builtin block registry is invalid")
+
+    }
+
+    /// The registry used by the game, which is the builtin registry unless set_global was called first
+    pub fn global() -> &'static Self {
+        GLOBAL_REGISTRY.get_or_init(Self::builtin)
+
+    }
+
+    /// Sets the registry used by the game, giving it back if the global registry was already in use
+    pub fn set_global(registry: Self) -> Result<(), Self> {
+        GLOBAL_REGISTRY.set(registry)
+
+    }
+
+    fn register(&mut self, mut properties: BlockProperties) -> Result<BlockId> {
+        ensure!(!self.ids.contains_key(&properties.name), "Block {} is defined more than once", properties.name);
+        // Saved chunks store each block's name after a single byte length
+        ensure!(properties.name.len() <= u8::MAX as usize, "Block {} has a name longer than {} bytes", properties.name, u8::MAX);
+
+        let id = BlockId(self.blocks.len().try_into().context("Too many block types")?);
+
+        // Air is never drawn, so it doesn't need any textures
+        if id != BlockId::AIR {
+            for (texture, texture_index) in properties.textures.iter().zip(properties.texture_indices.iter_mut()) {
+                *texture_index = match self.texture_names.iter().position(|name| name == texture) {
+                    Some(index) => index as u32,
+                    None => {
+                        self.texture_names.push(texture.clone());
+                        (self.texture_names.len() - 1) as u32
+
+                    },
+
+                };
+
+            }
+
+        }
+
+        self.ids.insert(properties.name.clone(), id);
+        self.blocks.push(properties);
+
+        Ok(id)
+
+    }
+
+    /// Looks up the id of a block by its name
+    pub fn id(&self, name: &str) -> Option<BlockId> {
+        self.ids.get(name).copied()
+
+    }
+
+    /// The properties of the given block, or air's properties if the id isn't registered
+    pub fn get(&self, id: BlockId) -> &BlockProperties {
+        self.blocks.get(usize::from(id.0)).unwrap_or(&self.blocks[0])
+
+    }
+
+    /// The name of every texture used by a block, indexed by BlockProperties::texture_index
+    pub fn texture_names(&self) -> &[String] {
+        &self.texture_names
+
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (BlockId, &BlockProperties)> {
+        self.blocks.iter().enumerate().map(|(id, properties)| (BlockId(id as u16), properties))
+
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCKS: &str = r#"
+        [[block]]
+        name = "dirt"
+        texture = "dirt"
+        hardness = 0.5
+
+        [[block]]
+        name = "grass"
+        texture = "dirt"
+        top = "grass_top"
+        side = "grass_side"
+
+        [[block]]
+        name = "leaves"
+        texture = "leaves"
+        transparent = true
+        render_mode = "cutout"
+
+        [[block]]
+        name = "water"
+        texture = "water"
+        solid = false
+        transparent = true
+        render_mode = "translucent"
+        hardness = -1.0
+
+        [[block]]
+        name = "glowstone"
+        texture = "glowstone"
+        light_emission = 15
+    "#;
+
+    fn registry() -> BlockRegistry {
+        BlockRegistry::from_toml_str(BLOCKS).unwrap()
+
+    }
+
+    // A registry with a single block, made of the given fields
+    fn parse_block(fields: &str) -> Result<BlockRegistry> {
+        BlockRegistry::from_toml_str(&format!("[[block]]\nname = \"block\"\n{fields}"))
+
+    }
+
+    #[test]
+    fn blocks_are_parsed_after_air() {
+        let registry = registry();
+
+        assert_eq!(registry.len(), 6);
+        assert_eq!(registry.id("air"), Some(BlockId::AIR));
+        assert_eq!(registry.id("dirt"), Some(BlockId(1)));
+        assert_eq!(registry.id("glowstone"), Some(BlockId(5)));
+        assert_eq!(registry.id("bedrock"), None);
+
+        let dirt = registry.get(registry.id("dirt").unwrap());
+        assert!(dirt.solid && !dirt.transparent);
+        assert_eq!(dirt.render_mode, RenderMode::Opaque);
+        assert_eq!(dirt.hardness, 0.5);
+        assert_eq!(dirt.light_emission, 0);
+
+        let water = registry.get(registry.id("water").unwrap());
+        assert!(!water.solid && water.transparent);
+        assert!(!water.is_breakable());
+
+        assert_eq!(registry.get(registry.id("glowstone").unwrap()).light_emission, 15);
+
+    }
+
+    #[test]
+    fn faces_fall_back_to_the_block_texture() {
+        let registry = registry();
+        let grass = registry.get(registry.id("grass").unwrap());
+
+        assert_eq!(grass.texture(Face::YPos), "grass_top");
+        assert_eq!(grass.texture(Face::YNeg), "dirt");
+
+        for face in [Face::XPos, Face::XNeg, Face::ZPos, Face::ZNeg] {
+            assert_eq!(grass.texture(face), "grass_side");
+
+        }
+
+        // Textures shared between blocks are only listed once
+        assert_eq!(registry.texture_names(), ["dirt", "grass_side", "grass_top", "leaves", "water", "glowstone"]);
+
+        for (_, properties) in registry.iter().skip(1) {
+            for face in Face::ALL {
+                assert_eq!(registry.texture_names()[properties.texture_index(face) as usize], properties.texture(face));
+
+            }
+
+        }
+
+    }
+
+    #[test]
+    fn unknown_ids_are_air() {
+        let registry = registry();
+
+        for id in [BlockId(6), BlockId(999), BlockId(u16::MAX)] {
+            assert_eq!(registry.get(id).name, "air");
+
+        }
+
+        let air = registry.get(BlockId::AIR);
+        assert!(!air.solid && air.transparent);
+
+    }
+
+    #[test]
+    fn render_modes_are_parsed() {
+        let registry = registry();
+        let render_mode = |name: &str| registry.get(registry.id(name).unwrap()).render_mode;
+
+        assert_eq!(render_mode("dirt"), RenderMode::Opaque);
+        assert_eq!(render_mode("leaves"), RenderMode::Cutout);
+        assert_eq!(render_mode("water"), RenderMode::Translucent);
+
+        assert!(parse_block("texture = \"a\"\nrender_mode = \"opaque\"").is_ok());
+        assert!(parse_block("texture = \"a\"\ntransparent = true\nrender_mode = \"glowing\"").is_err());
+        // Capitalised names aren't accepted
+        assert!(parse_block("texture = \"a\"\ntransparent = true\nrender_mode = \"Cutout\"").is_err());
+
+    }
+
+    #[test]
+    fn invalid_blocks_are_rejected() {
+        // Blocks that can be seen through have to be transparent
+        assert!(parse_block("texture = \"a\"\nrender_mode = \"cutout\"").is_err());
+        assert!(parse_block("top = \"a\"\nside = \"a\"").is_err());
+        assert!(parse_block("texture = \"a\"\nlight_emission = 16").is_err());
+        assert!(parse_block("texture = \"a\"\ncolour = \"red\"").is_err());
+        assert!(BlockRegistry::from_toml_str("[[block]]\nname = \"air\"\ntexture = \"a\"").is_err());
+        assert!(BlockRegistry::from_toml_str("[[block]]\nname = \"a\"\ntexture = \"a\"\n[[block]]\nname = \"a\"\ntexture = \"b\"").is_err());
+
+    }
+
+    #[test]
+    fn the_builtin_registry_is_valid() {
+        let registry = BlockRegistry::builtin();
+
+        assert!(registry.id("stone").is_some());
+        assert!(registry.id("glowstone").is_some());
+
+    }
+
+}
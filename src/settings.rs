@@ -0,0 +1,66 @@
+use crate::mesh::Mesher;
+
+/// Options that change how the game plays
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// The seed the terrain is generated from. The same seed always generates the same world
+    pub world_seed: u64,
+    /// How many times per second the simulation is updated, independent of the frame rate
+    pub ticks_per_second: u32,
+    /// How fast the player moves while walking, in world units per second
+    pub walk_speed: f32,
+    /// How fast the player moves while flying, in world units per second
+    pub fly_speed: f32,
+    /// How fast falling speeds up, in world units per second squared
+    pub gravity: f32,
+    /// How fast the player moves up when they jump, in world units per second
+    pub jump_speed: f32,
+    /// How far away blocks can be broken and placed from, in world units
+    pub reach: f32,
+    /// How many radians the camera turns per pixel the mouse moves
+    pub mouse_sensitivity: f32,
+    /// How chunks are turned into meshes
+    pub mesher: Mesher,
+    /// Whether the corners of faces next to opaque blocks are darkened
+    pub ambient_occlusion: bool,
+    /// How many chunks away from the camera chunks are loaded, horizontally
+    pub render_distance: u32,
+    /// How many chunks above and below the camera chunks are loaded
+    pub vertical_render_distance: u32,
+    /// Roughly how many bytes loaded chunks can take up. The farthest chunks are unloaded to stay under it
+    pub chunk_memory_budget: usize,
+    /// Whether chunks are saved when they're unloaded, so changes to them are kept
+    pub save_chunks: bool,
+    /// The most chunks that are generated at once, so chunks that end up nearest the camera aren't stuck behind a long queue
+    pub max_generating_chunks: usize,
+    /// The most chunk meshes that are uploaded to the GPU each frame, so a burst of finished chunks doesn't cause a stutter
+    pub max_chunk_uploads_per_frame: usize,
+
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            world_seed: 0,
+            ticks_per_second: 60,
+            walk_speed: 8.6,
+            fly_speed: 20.0,
+            // A jump reaches a little over a block high
+            gravity: 64.0,
+            jump_speed: 17.0,
+            reach: 10.0,
+            mouse_sensitivity: 0.002,
+            mesher: Mesher::default(),
+            ambient_occlusion: true,
+            render_distance: 8,
+            vertical_render_distance: 4,
+            chunk_memory_budget: 512 * 1024 * 1024,
+            save_chunks: true,
+            max_generating_chunks: 16,
+            max_chunk_uploads_per_frame: 8,
+
+        }
+
+    }
+
+}
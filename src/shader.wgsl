@@ -7,14 +7,33 @@ struct CameraUniform {
 @group(1) @binding(0)
 var<uniform> camera: CameraUniform;
 
+struct LightUniform {
+	// Points from the world towards the sun
+	sun_direction: vec3<f32>,
+	color: vec3<f32>,
+	ambient: f32,
+};
+
+@group(1) @binding(1)
+var<uniform> light: LightUniform;
+
 struct VertexInput {
     @location(0) position: vec3<f32>,
  	@location(1) tex_coords: vec2<f32>,
+ 	@location(2) texture: u32,
+ 	@location(3) normal: vec3<f32>,
+ 	// Sky light and block light, from 0 to 1
+ 	@location(4) light: vec2<f32>,
+ 	@location(5) ambient_occlusion: f32,
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
+    @location(1) @interpolate(flat) texture: u32,
+    @location(2) normal: vec3<f32>,
+    @location(3) light: vec2<f32>,
+    @location(4) ambient_occlusion: f32,
 };
 
 @vertex
@@ -23,18 +42,77 @@ fn vs_main(
 ) -> VertexOutput {
     var out: VertexOutput;
     out.tex_coords = model.tex_coords;
+    out.texture = model.texture;
+    out.normal = model.normal;
+    out.light = model.light;
+    out.ambient_occlusion = model.ambient_occlusion;
     out.clip_position = camera.view_proj * vec4<f32>(model.position, 1.0);
     return out;
 }
 
 // Fragment shader
 
+// The color of light given off by blocks
+let BLOCK_LIGHT_COLOR: vec3<f32> = vec3<f32>(1.0, 0.9, 0.75);
+// How bright a face in complete darkness is, so caves aren't pitch black
+let MIN_BRIGHTNESS: f32 = 0.04;
+// How bright a fully occluded corner is
+let MIN_AMBIENT_OCCLUSION: f32 = 0.45;
+
+// Each light level is 80% as bright as the one above it
+fn light_brightness(level: f32) -> f32 {
+    return pow(0.8, (1.0 - level) * 15.0);
+}
+
 @group(0) @binding(0)
 var t_diffuse: texture_2d<f32>;
 @group(0) @binding(1)
 var s_diffuse: sampler;
 
+// Where each texture is in the atlas, as the min UV in xy and the size in zw
+struct AtlasUniform {
+	rects: array<vec4<f32>, 256>,
+};
+
+@group(0) @binding(2)
+var<uniform> atlas: AtlasUniform;
+
+// The lit color of a fragment, with the texture's alpha
+fn shade(in: VertexOutput) -> vec4<f32> {
+    let rect = atlas.rects[in.texture];
+    // Repeat the texture across the face, since tex_coords can go past 1
+    let atlas_coords = rect.xy + fract(in.tex_coords) * rect.zw;
+
+    let color = textureSample(t_diffuse, s_diffuse, atlas_coords);
+
+    // Lambert shading, where faces get brighter the more directly they face the sun.
+    // Ambient light keeps faces pointing away from the sun from going black
+    let diffuse = max(dot(normalize(in.normal), light.sun_direction), 0.0);
+    let sunlight = light.ambient + (1.0 - light.ambient) * diffuse * light.color;
+
+    // Sunlight only reaches as far as sky light does, and block light lights every side of a block evenly
+    let sky = sunlight * light_brightness(in.light.x);
+    let block = BLOCK_LIGHT_COLOR * light_brightness(in.light.y);
+    let occlusion = mix(MIN_AMBIENT_OCCLUSION, 1.0, in.ambient_occlusion);
+    let lighting = max(max(sky, block), vec3<f32>(MIN_BRIGHTNESS)) * occlusion;
+
+    return vec4<f32>(color.rgb * lighting, color.a);
+}
+
+// Opaque and cutout faces, where cutout textures leave out any pixels that are mostly see through
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    let color = shade(in);
+
+    if (color.a < 0.5) {
+        discard;
+    }
+
+    return vec4<f32>(color.rgb, 1.0);
+}
+
+// Translucent faces, which are blended with whatever is behind them
+@fragment
+fn fs_translucent(in: VertexOutput) -> @location(0) vec4<f32> {
+    return shade(in);
 }
@@ -0,0 +1,677 @@
+use std::collections::HashMap;
+#[cfg(not(target_arch="wasm32"))]
+use std::io::{Read, Seek, SeekFrom};
+#[cfg(not(target_arch="wasm32"))]
+use std::path::{Path, PathBuf};
+
+use anyhow::*;
+use glam::IVec3;
+
+use crate::block::{BlockId, Chunk, CHUNK_SIZE};
+use crate::registry::BlockRegistry;
+
+/// How many chunks long each side of a region is. Regions are one chunk tall
+pub const REGION_SIZE: i32 = 32;
+const REGION_CHUNKS: usize = (REGION_SIZE * REGION_SIZE) as usize;
+const REGION_MAGIC: &[u8; 4] = b"FCRG";
+const REGION_VERSION: u32 = 2;
+// The magic, the version, then an offset and length for every chunk
+const REGION_HEADER_SIZE: usize = 8 + REGION_CHUNKS * 8;
+
+/// Somewhere chunks can be saved to and loaded from
+pub trait WorldStorage: Send {
+    /// Loads a chunk, or returns None if it has never been saved
+    fn load_chunk(&mut self, chunk_coord: IVec3) -> Result<Option<Chunk>>;
+    fn save_chunk(&mut self, chunk_coord: IVec3, chunk: &Chunk) -> Result<()>;
+    /// Makes sure every saved chunk has been written out
+    fn flush(&mut self) -> Result<()>;
+
+}
+
+/// Compresses a chunk's blocks as runs of the same block, which is most of a chunk.
+/// Blocks are saved by name, since their ids change whenever blocks.toml is edited. The data starts with a little endian u16 count
+/// of the names of the blocks in the chunk, each a u8 length followed by the name. Then each run is a u16 length followed by the u16 index of its block's name
+pub fn encode_chunk(chunk: &Chunk, registry: &BlockRegistry) -> Vec<u8> {
+    let mut names: Vec<BlockId> = Vec::new();
+    let mut runs = Vec::new();
+    let mut blocks = chunk.blocks().peekable();
+
+    while let Some(block) = blocks.next() {
+        let mut run_length: u16 = 1;
+
+        while run_length < u16::MAX && blocks.peek() == Some(&block) {
+            blocks.next();
+            run_length += 1;
+
+        }
+
+        let name_index = match names.iter().position(|name| *name == block) {
+            Some(name_index) => name_index,
+            None => {
+                names.push(block);
+                names.len() - 1
+
+            },
+
+        };
+
+        runs.extend_from_slice(&run_length.to_le_bytes());
+        runs.extend_from_slice(&(name_index as u16).to_le_bytes());
+
+    }
+
+    let mut bytes = Vec::with_capacity(2 + names.len() * 16 + runs.len());
+    bytes.extend_from_slice(&(names.len() as u16).to_le_bytes());
+
+    for block in names {
+        let name = registry.get(block).name.as_bytes();
+        bytes.push(name.len() as u8);
+        bytes.extend_from_slice(name);
+
+    }
+
+    bytes.extend_from_slice(&runs);
+    bytes
+
+}
+
+/// Decompresses a chunk written by encode_chunk, looking up its blocks by name in the registry.
+/// Chunks with blocks that aren't in the registry can't be loaded, rather than having them quietly replaced
+pub fn decode_chunk(chunk_coord: IVec3, bytes: &[u8], registry: &BlockRegistry) -> Result<Chunk> {
+    let name_count = bytes.get(0..2).context("Chunk data is missing its block names")?;
+    let name_count = u16::from_le_bytes([name_count[0], name_count[1]]) as usize;
+    let mut offset = 2;
+    let mut names = Vec::with_capacity(name_count);
+
+    for _ in 0..name_count {
+        let length = *bytes.get(offset).context("Chunk data ends partway through its block names")? as usize;
+        let name = bytes.get(offset + 1..offset + 1 + length).context("Chunk data ends partway through its block names")?;
+        let name = std::str::from_utf8(name).context("Chunk data has a block name that isn't valid UTF-8")?;
+
+        names.push(registry.id(name).with_context(|| format!("Chunk has a block named {name}, which isn't in the block registry"))?);
+        offset += 1 + length;
+
+    }
+
+    let runs = &bytes[offset..];
+
+    if !runs.len().is_multiple_of(4) {
+        bail!("Chunk data has {} bytes of runs, which isn't a whole number of runs", runs.len());
+
+    }
+
+    let mut blocks = Vec::with_capacity(CHUNK_SIZE);
+
+    for run in runs.chunks_exact(4) {
+        let run_length = u16::from_le_bytes([run[0], run[1]]) as usize;
+        let name_index = u16::from_le_bytes([run[2], run[3]]) as usize;
+        let block = *names.get(name_index).with_context(|| format!("Chunk data has a run of block {name_index}, but only {name_count} block names"))?;
+
+        if blocks.len() + run_length > CHUNK_SIZE {
+            bail!("Chunk data has more than {CHUNK_SIZE} blocks");
+
+        }
+
+        blocks.extend(std::iter::repeat_n(block, run_length));
+
+    }
+
+    if blocks.len() != CHUNK_SIZE {
+        bail!("Chunk data has {} blocks instead of {CHUNK_SIZE}", blocks.len());
+
+    }
+
+    Ok(Chunk::from_blocks(chunk_coord, blocks.into_iter()))
+
+}
+
+/// The region a chunk is saved in, and its index within that region
+pub fn region_coord(chunk_coord: IVec3) -> (IVec3, usize) {
+    let region_coord = IVec3::new(chunk_coord.x.div_euclid(REGION_SIZE), chunk_coord.y, chunk_coord.z.div_euclid(REGION_SIZE));
+    let index = chunk_coord.x.rem_euclid(REGION_SIZE) + chunk_coord.z.rem_euclid(REGION_SIZE) * REGION_SIZE;
+
+    (region_coord, index as usize)
+
+}
+
+/// A group of chunks saved together in one file.
+/// The file starts with a header giving the offset and length of each chunk's encoded data, with a length of 0 for chunks that haven't been saved
+#[derive(Clone, Debug)]
+pub struct RegionFile {
+    chunks: Vec<Option<Vec<u8>>>,
+
+}
+
+impl RegionFile {
+    pub fn new() -> Self {
+        Self {
+            chunks: vec![None; REGION_CHUNKS],
+
+        }
+
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut region = Self::new();
+
+        for (index, (chunk, (offset, length))) in region.chunks.iter_mut().zip(Self::read_header(bytes)?).enumerate() {
+            if length == 0 {
+                continue;
+
+            }
+
+            let data = bytes.get(offset..offset + length).with_context(|| format!("Chunk {index} is past the end of the region file"))?;
+            *chunk = Some(data.to_vec());
+
+        }
+
+        Ok(region)
+
+    }
+
+    /// Checks the start of a region file is a header this version can read, and returns the offset and length of every chunk's data
+    pub fn read_header(bytes: &[u8]) -> Result<Vec<(usize, usize)>> {
+        if bytes.len() < REGION_HEADER_SIZE || &bytes[0..4] != REGION_MAGIC {
+            bail!("Not a region file");
+
+        }
+
+        let read_u32 = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize;
+
+        let version = read_u32(4);
+
+        if version != REGION_VERSION as usize {
+            bail!("Unsupported region file version {version}");
+
+        }
+
+        Ok((0..REGION_CHUNKS).map(|index| (read_u32(8 + index * 8), read_u32(12 + index * 8))).collect())
+
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(REGION_HEADER_SIZE);
+        let mut data = Vec::new();
+
+        header.extend_from_slice(REGION_MAGIC);
+        header.extend_from_slice(&REGION_VERSION.to_le_bytes());
+
+        for chunk in self.chunks.iter() {
+            let (offset, length) = match chunk {
+                Some(chunk) => (REGION_HEADER_SIZE + data.len(), chunk.len()),
+                None => (0, 0),
+
+            };
+
+            header.extend_from_slice(&(offset as u32).to_le_bytes());
+            header.extend_from_slice(&(length as u32).to_le_bytes());
+
+            if let Some(chunk) = chunk {
+                data.extend_from_slice(chunk);
+
+            }
+
+        }
+
+        header.extend_from_slice(&data);
+        header
+
+    }
+
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        self.chunks[index].as_deref()
+
+    }
+
+    pub fn set(&mut self, index: usize, data: Vec<u8>) {
+        self.chunks[index] = Some(data);
+
+    }
+
+}
+
+impl Default for RegionFile {
+    fn default() -> Self {
+        Self::new()
+
+    }
+
+}
+
+/// Saves chunks in region files in a directory.
+/// Only chunks that haven't been written out yet are kept in memory, and loading a chunk just reads its part of the region file
+#[cfg(not(target_arch="wasm32"))]
+pub struct FileStorage {
+    dir: PathBuf,
+    // The encoded chunks that haven't been written to disk yet, by region and then by index in the region
+    unsaved_chunks: HashMap<IVec3, HashMap<usize, Vec<u8>>>,
+
+}
+
+#[cfg(not(target_arch="wasm32"))]
+impl FileStorage {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            unsaved_chunks: HashMap::new(),
+
+        }
+
+    }
+
+    fn region_path(&self, region_coord: IVec3) -> PathBuf {
+        self.dir.join(format!("r.{}.{}.{}.region", region_coord.x, region_coord.y, region_coord.z))
+
+    }
+
+    // Reads a single chunk's data from a region file, without reading the rest of the file
+    fn read_chunk(path: &Path, index: usize) -> Result<Option<Vec<u8>>> {
+        let mut file = match std::fs::File::open(path) {
+            std::result::Result::Ok(file) => file,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error.into()),
+
+        };
+
+        let mut header = Vec::with_capacity(REGION_HEADER_SIZE);
+        file.by_ref().take(REGION_HEADER_SIZE as u64).read_to_end(&mut header)?;
+
+        let (offset, length) = RegionFile::read_header(&header)?[index];
+
+        if length == 0 {
+            return Ok(None);
+
+        }
+
+        let mut data = vec![0; length];
+        file.seek(SeekFrom::Start(offset as u64))?;
+        file.read_exact(&mut data).with_context(|| format!("Chunk {index} is past the end of the region file"))?;
+
+        Ok(Some(data))
+
+    }
+
+    fn read_region(path: &Path) -> Result<RegionFile> {
+        match std::fs::read(path) {
+            std::result::Result::Ok(bytes) => RegionFile::from_bytes(&bytes),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(RegionFile::new()),
+            Err(error) => Err(error.into()),
+
+        }
+
+    }
+
+    // Writes the region to a temporary file first and then moves it over the old one, so the region is never left half written
+    fn write_region(path: &Path, region: &RegionFile) -> Result<()> {
+        let temp_path = path.with_extension("region.tmp");
+
+        std::fs::write(&temp_path, region.to_bytes())?;
+        std::fs::rename(&temp_path, path)?;
+
+        Ok(())
+
+    }
+
+}
+
+#[cfg(not(target_arch="wasm32"))]
+impl WorldStorage for FileStorage {
+    fn load_chunk(&mut self, chunk_coord: IVec3) -> Result<Option<Chunk>> {
+        let (region_coord, index) = region_coord(chunk_coord);
+
+        let bytes = match self.unsaved_chunks.get(&region_coord).and_then(|chunks| chunks.get(&index)) {
+            Some(bytes) => Some(bytes.clone()),
+            None => {
+                let path = self.region_path(region_coord);
+                Self::read_chunk(&path, index).with_context(|| format!("Failed to read {}", path.display()))?
+
+            },
+
+        };
+
+        bytes.map(|bytes| decode_chunk(chunk_coord, &bytes, BlockRegistry::global())).transpose()
+
+    }
+
+    fn save_chunk(&mut self, chunk_coord: IVec3, chunk: &Chunk) -> Result<()> {
+        let (region_coord, index) = region_coord(chunk_coord);
+
+        self.unsaved_chunks.entry(region_coord).or_default().insert(index, encode_chunk(chunk, BlockRegistry::global()));
+
+        Ok(())
+
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.unsaved_chunks.is_empty() {
+            return Ok(());
+
+        }
+
+        std::fs::create_dir_all(&self.dir).with_context(|| format!("Failed to create {}", self.dir.display()))?;
+
+        for region_coord in self.unsaved_chunks.keys().copied().collect::<Vec<_>>() {
+            let path = self.region_path(region_coord);
+            let mut region = Self::read_region(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+
+            for (index, data) in self.unsaved_chunks[&region_coord].iter() {
+                region.set(*index, data.clone());
+
+            }
+
+            Self::write_region(&path, &region).with_context(|| format!("Failed to write {}", path.display()))?;
+            // Once the chunks are on disk they're read back from there, so they don't need to stay in memory
+            self.unsaved_chunks.remove(&region_coord);
+
+        }
+
+        Ok(())
+
+    }
+
+}
+
+/// Keeps saved chunks in memory, for when there's nowhere to write files to
+#[derive(Default)]
+pub struct MemoryStorage {
+    chunks: HashMap<IVec3, Vec<u8>>,
+
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+
+    }
+
+}
+
+impl WorldStorage for MemoryStorage {
+    fn load_chunk(&mut self, chunk_coord: IVec3) -> Result<Option<Chunk>> {
+        self.chunks.get(&chunk_coord)
+            .map(|bytes| decode_chunk(chunk_coord, bytes, BlockRegistry::global()))
+            .transpose()
+
+    }
+
+    fn save_chunk(&mut self, chunk_coord: IVec3, chunk: &Chunk) -> Result<()> {
+        self.chunks.insert(chunk_coord, encode_chunk(chunk, BlockRegistry::global()));
+        Ok(())
+
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::block::{LocalPos, CHUNK_SIZE_AXIS};
+
+    use super::*;
+
+    fn registry_with(names: &[&str]) -> BlockRegistry {
+        let toml_str: String = names.iter().map(|name| format!("[[block]]\nname = \"{name}\"\ntexture = \"{name}\"\n")).collect();
+        BlockRegistry::from_toml_str(&toml_str).unwrap()
+
+    }
+
+    // A chunk with layers of a few different blocks, and some single blocks breaking up the runs
+    fn layered_chunk(chunk_coord: IVec3, registry: &BlockRegistry, names: &[&str]) -> Chunk {
+        let mut chunk = Chunk::new(chunk_coord);
+
+        for local_pos in LocalPos::all::<CHUNK_SIZE_AXIS>() {
+            let name = match (local_pos.x + local_pos.z) % 7 == 0 {
+                true => names[0],
+                false => names[local_pos.y as usize % names.len()],
+
+            };
+
+            chunk.set_block(local_pos, registry.id(name).unwrap());
+
+        }
+
+        chunk
+
+    }
+
+    fn blocks(chunk: &Chunk) -> Vec<BlockId> {
+        chunk.blocks().collect()
+
+    }
+
+    #[test]
+    fn chunks_round_trip() {
+        let registry = BlockRegistry::global();
+        let chunk_coord = IVec3::new(-3, 1, 40);
+
+        for chunk in [Chunk::new(chunk_coord), layered_chunk(chunk_coord, registry, &["air", "stone", "dirt", "water"])] {
+            let decoded = decode_chunk(chunk_coord, &encode_chunk(&chunk, registry), registry).unwrap();
+
+            assert_eq!(blocks(&decoded), blocks(&chunk));
+            assert_eq!(decoded.world_pos(), chunk.world_pos());
+
+        }
+
+    }
+
+    #[test]
+    fn blocks_are_saved_by_name() {
+        let saved_with = registry_with(&["dirt", "stone"]);
+        // Blocks have been added and reordered since the chunk was saved
+        let loaded_with = registry_with(&["glass", "stone", "sand", "dirt"]);
+
+        let chunk = layered_chunk(IVec3::ZERO, &saved_with, &["dirt", "stone", "air"]);
+        let decoded = decode_chunk(IVec3::ZERO, &encode_chunk(&chunk, &saved_with), &loaded_with).unwrap();
+
+        let names = |chunk: &Chunk, registry: &BlockRegistry| chunk.blocks().map(|block| registry.get(block).name.clone()).collect::<Vec<_>>();
+
+        assert_eq!(names(&decoded, &loaded_with), names(&chunk, &saved_with));
+        assert_ne!(blocks(&decoded), blocks(&chunk));
+
+    }
+
+    #[test]
+    fn chunks_with_unknown_blocks_fail_to_load() {
+        let saved_with = registry_with(&["dirt", "stone"]);
+        let loaded_with = registry_with(&["dirt"]);
+
+        let chunk = layered_chunk(IVec3::ZERO, &saved_with, &["dirt", "stone"]);
+        let error = decode_chunk(IVec3::ZERO, &encode_chunk(&chunk, &saved_with), &loaded_with).err().unwrap();
+
+        assert!(error.to_string().contains("stone"), "{error}");
+
+    }
+
+    #[test]
+    fn corrupt_chunk_data_fails_to_load() {
+        let registry = BlockRegistry::global();
+        let bytes = encode_chunk(&layered_chunk(IVec3::ZERO, registry, &["stone", "dirt"]), registry);
+
+        // Cut off partway through the names, partway through a run, and missing whole runs
+        for length in [0, 1, 4, bytes.len() - 1, bytes.len() - 4] {
+            assert!(decode_chunk(IVec3::ZERO, &bytes[..length], registry).is_err(), "{length} bytes");
+
+        }
+
+        // A run of a block past the end of the names
+        let mut bad_index = bytes.clone();
+        let last = bad_index.len() - 2;
+        bad_index[last..].copy_from_slice(&100_u16.to_le_bytes());
+        assert!(decode_chunk(IVec3::ZERO, &bad_index, registry).is_err());
+
+        // An extra run on the end
+        let mut too_long = bytes.clone();
+        too_long.extend_from_slice(&[1, 0, 0, 0]);
+        assert!(decode_chunk(IVec3::ZERO, &too_long, registry).is_err());
+
+    }
+
+    #[test]
+    fn region_coords_wrap_into_regions() {
+        assert_eq!(region_coord(IVec3::new(0, 0, 0)), (IVec3::new(0, 0, 0), 0));
+        assert_eq!(region_coord(IVec3::new(31, 5, 31)), (IVec3::new(0, 5, 0), 1023));
+        assert_eq!(region_coord(IVec3::new(32, -2, 0)), (IVec3::new(1, -2, 0), 0));
+        assert_eq!(region_coord(IVec3::new(-1, 0, -1)), (IVec3::new(-1, 0, -1), 1023));
+        assert_eq!(region_coord(IVec3::new(-32, 0, 1)), (IVec3::new(-1, 0, 0), 32));
+
+    }
+
+    #[test]
+    fn region_files_round_trip() {
+        let mut region = RegionFile::new();
+        region.set(0, vec![1, 2, 3]);
+        region.set(17, vec![4; 100]);
+        region.set(REGION_CHUNKS - 1, vec![5]);
+
+        let read = RegionFile::from_bytes(&region.to_bytes()).unwrap();
+
+        for index in 0..REGION_CHUNKS {
+            assert_eq!(read.get(index), region.get(index), "chunk {index}");
+
+        }
+
+        assert_eq!(RegionFile::from_bytes(&RegionFile::new().to_bytes()).unwrap().get(0), None);
+
+    }
+
+    #[test]
+    fn region_files_with_bad_headers_fail_to_load() {
+        let bytes = RegionFile::new().to_bytes();
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert!(RegionFile::from_bytes(&bad_magic).unwrap_err().to_string().contains("Not a region file"));
+
+        assert!(RegionFile::from_bytes(&bytes[..REGION_HEADER_SIZE - 1]).unwrap_err().to_string().contains("Not a region file"));
+        assert!(RegionFile::from_bytes(&[]).is_err());
+
+        let mut old_version = bytes.clone();
+        old_version[4..8].copy_from_slice(&1_u32.to_le_bytes());
+        assert!(RegionFile::from_bytes(&old_version).unwrap_err().to_string().contains("version 1"));
+
+    }
+
+    #[test]
+    fn region_files_with_chunks_past_the_end_fail_to_load() {
+        let mut region = RegionFile::new();
+        region.set(5, vec![7; 64]);
+
+        let bytes = region.to_bytes();
+        let error = RegionFile::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err();
+
+        assert!(error.to_string().contains("Chunk 5 is past the end"), "{error}");
+
+    }
+
+    // A directory for a test to save into, which is deleted when the test ends
+    #[cfg(not(target_arch="wasm32"))]
+    struct TempDir(PathBuf);
+
+    #[cfg(not(target_arch="wasm32"))]
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("freecraft-{name}-{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&path);
+
+            Self(path)
+
+        }
+
+    }
+
+    #[cfg(not(target_arch="wasm32"))]
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+
+        }
+
+    }
+
+    #[test]
+    #[cfg(not(target_arch="wasm32"))]
+    fn file_storage_saves_and_loads_chunks() {
+        let dir = TempDir::new("file-storage");
+        let registry = BlockRegistry::global();
+
+        // Two chunks in the same region and one in another
+        let chunks: Vec<(IVec3, Chunk)> = [IVec3::new(0, 0, 0), IVec3::new(3, 0, -1), IVec3::new(40, -2, 7)].into_iter()
+            .map(|chunk_coord| (chunk_coord, layered_chunk(chunk_coord, registry, &["stone", "sand", "air"])))
+            .collect();
+
+        let mut storage = FileStorage::new(&dir.0);
+
+        for (chunk_coord, chunk) in chunks.iter() {
+            storage.save_chunk(*chunk_coord, chunk).unwrap();
+
+        }
+
+        // Chunks that haven't been written out yet can still be loaded
+        assert_eq!(blocks(&storage.load_chunk(chunks[0].0).unwrap().unwrap()), blocks(&chunks[0].1));
+
+        storage.flush().unwrap();
+        assert!(storage.unsaved_chunks.is_empty());
+
+        // Saving another chunk to a region that's already on disk keeps the chunks that were there
+        let extra = Chunk::filled(IVec3::new(1, 0, 0), registry.id("dirt").unwrap());
+        storage.save_chunk(IVec3::new(1, 0, 0), &extra).unwrap();
+        storage.flush().unwrap();
+
+        let mut reopened = FileStorage::new(&dir.0);
+
+        for (chunk_coord, chunk) in chunks.iter().chain([(IVec3::new(1, 0, 0), extra)].iter()) {
+            let loaded = reopened.load_chunk(*chunk_coord).unwrap().unwrap();
+            assert_eq!(blocks(&loaded), blocks(chunk), "chunk {chunk_coord}");
+
+        }
+
+        assert!(reopened.load_chunk(IVec3::new(2, 0, 0)).unwrap().is_none());
+        assert!(reopened.load_chunk(IVec3::new(0, 9, 0)).unwrap().is_none());
+
+        // Nothing is left over from writing the regions
+        for entry in std::fs::read_dir(&dir.0).unwrap() {
+            let path = entry.unwrap().path();
+            assert_eq!(path.extension().unwrap(), "region", "{}", path.display());
+
+        }
+
+    }
+
+    #[test]
+    #[cfg(not(target_arch="wasm32"))]
+    fn file_storage_reports_corrupt_regions() {
+        let dir = TempDir::new("corrupt-region");
+        let mut storage = FileStorage::new(&dir.0);
+
+        storage.save_chunk(IVec3::ZERO, &Chunk::new(IVec3::ZERO)).unwrap();
+        storage.flush().unwrap();
+
+        let path = storage.region_path(IVec3::ZERO);
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+
+        assert!(storage.load_chunk(IVec3::ZERO).is_err());
+
+        std::fs::write(&path, b"not a region").unwrap();
+        assert!(storage.load_chunk(IVec3::ZERO).is_err());
+
+    }
+
+    #[test]
+    fn memory_storage_saves_and_loads_chunks() {
+        let registry = BlockRegistry::global();
+        let chunk = layered_chunk(IVec3::ONE, registry, &["dirt", "air"]);
+        let mut storage = MemoryStorage::new();
+
+        assert!(storage.load_chunk(IVec3::ONE).unwrap().is_none());
+
+        storage.save_chunk(IVec3::ONE, &chunk).unwrap();
+        assert_eq!(blocks(&storage.load_chunk(IVec3::ONE).unwrap().unwrap()), blocks(&chunk));
+
+    }
+
+}
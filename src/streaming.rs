@@ -0,0 +1,521 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use glam::{IVec3, Vec3};
+use instant::{Duration, Instant};
+
+use crate::block::Chunk;
+use crate::jobs::JobSystem;
+use crate::settings::Settings;
+use crate::storage::WorldStorage;
+use crate::terrain::TerrainGenerator;
+use crate::world::World;
+
+// How often unloaded chunks are written out to storage. They're held onto in between, so chunks unloading every frame doesn't mean writing every frame
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+// Chunks evicted to stay within the memory budget may be loaded again once memory usage falls below this fraction of it
+const BUDGET_RELOAD_FRACTION: f64 = 0.75;
+
+/// Loads the chunks around a position as it moves through the world, and unloads the ones it leaves behind.
+/// Chunks are loaded from storage if they've been saved before, and generated otherwise
+pub struct ChunkStreamer {
+    generator: Arc<dyn TerrainGenerator>,
+    // Shared with the job system, so chunks can be loaded from storage away from the main thread
+    storage: Arc<Mutex<Box<dyn WorldStorage>>>,
+    // The chunk the load queue was built around
+    center: Option<IVec3>,
+    // Chunks waiting to be generated, sorted farthest first so the nearest can be popped off the end
+    load_queue: Vec<IVec3>,
+    // Chunks that were in range but unloaded to stay within the memory budget. They aren't queued again while they're in range,
+    // otherwise they'd be regenerated and evicted again every time the center moves
+    evicted: HashSet<IVec3>,
+    last_flush: Instant,
+
+}
+
+impl ChunkStreamer {
+    pub fn new(generator: Arc<dyn TerrainGenerator>, storage: Box<dyn WorldStorage>) -> Self {
+        Self {
+            generator,
+            storage: Arc::new(Mutex::new(storage)),
+            center: None,
+            load_queue: Vec::new(),
+            evicted: HashSet::new(),
+            last_flush: Instant::now(),
+
+        }
+
+    }
+
+    /// The coordinate of the chunk containing the given world position
+    pub fn chunk_coord(world_pos: Vec3) -> IVec3 {
+        World::chunk_and_local_pos(World::block_coord(world_pos)).0
+
+    }
+
+    // Whether a chunk is within the render distance of the center, plus margin chunks
+    fn in_range(settings: &Settings, center: IVec3, chunk_coord: IVec3, margin: i32) -> bool {
+        let offset = chunk_coord - center;
+        let radius = settings.render_distance as i32 + margin;
+
+        offset.x * offset.x + offset.z * offset.z <= radius * radius && offset.y.abs() <= settings.vertical_render_distance as i32 + margin
+
+    }
+
+    fn distance_squared(center: IVec3, chunk_coord: IVec3) -> i32 {
+        let offset = chunk_coord - center;
+        offset.dot(offset)
+
+    }
+
+    /// The number of chunks waiting to be generated
+    pub fn queued(&self) -> usize {
+        self.load_queue.len()
+
+    }
+
+    /// Queues the chunks around pos to be loaded nearest first, and unloads chunks that are out of range or over the memory budget
+    pub fn update(&mut self, world: &mut World, jobs: &mut JobSystem, pos: Vec3, settings: &Settings) {
+        let center = Self::chunk_coord(pos);
+
+        // Chunks get a one chunk margin before they're unloaded, so moving back and forth over a chunk border doesn't keep reloading them
+        let out_of_range: Vec<IVec3> = world.chunks()
+            .map(|(chunk_coord, _)| *chunk_coord)
+            .filter(|chunk_coord| !Self::in_range(settings, center, *chunk_coord, 1))
+            .collect();
+
+        let mut unloaded: Vec<(IVec3, Chunk)> = out_of_range.into_iter()
+            .filter_map(|chunk_coord| world.remove_chunk(chunk_coord).map(|chunk| (chunk_coord, chunk)))
+            .collect();
+
+        let mut memory_usage = world.memory_usage();
+
+        // Drop the farthest chunks until the rest fit in the budget
+        if memory_usage > settings.chunk_memory_budget {
+            let mut loaded: Vec<IVec3> = world.chunks().map(|(chunk_coord, _)| *chunk_coord).collect();
+            loaded.sort_unstable_by_key(|chunk_coord| -Self::distance_squared(center, *chunk_coord));
+
+            for chunk_coord in loaded {
+                if memory_usage <= settings.chunk_memory_budget {
+                    break;
+
+                }
+
+                if let Some(chunk) = world.remove_chunk(chunk_coord) {
+                    memory_usage -= chunk.memory_usage();
+                    unloaded.push((chunk_coord, chunk));
+                    self.evicted.insert(chunk_coord);
+
+                }
+
+            }
+
+            self.load_queue.retain(|chunk_coord| !self.evicted.contains(chunk_coord));
+
+        }
+
+        self.evicted.retain(|chunk_coord| Self::in_range(settings, center, *chunk_coord, 1));
+
+        // Once there's plenty of room again, the evicted chunks can come back
+        if !self.evicted.is_empty() && (memory_usage as f64) < settings.chunk_memory_budget as f64 * BUDGET_RELOAD_FRACTION {
+            self.evicted.clear();
+            self.center = None;
+
+        }
+
+        if settings.save_chunks {
+            // Chunks that haven't been edited can be loaded or generated again just the same, so they aren't written out
+            for (chunk_coord, chunk) in unloaded.iter().filter(|(_, chunk)| chunk.has_unsaved_edits()) {
+                self.save_chunk(*chunk_coord, chunk);
+
+            }
+
+            if self.last_flush.elapsed() >= SAVE_INTERVAL {
+                self.flush();
+
+            }
+
+        }
+
+        if self.center != Some(center) {
+            self.center = Some(center);
+            self.rebuild_load_queue(world, center, settings);
+
+        }
+
+        // Only a few chunks are loaded at once, so the queue can be reordered when the center moves
+        while jobs.generating_count() < settings.max_generating_chunks && memory_usage < settings.chunk_memory_budget {
+            let chunk_coord = match self.load_queue.pop() {
+                Some(chunk_coord) => chunk_coord,
+                None => break,
+
+            };
+
+            if world.chunk(chunk_coord).is_some() || jobs.is_generating(chunk_coord) || self.evicted.contains(&chunk_coord) {
+                continue;
+
+            }
+
+            // Saved chunks are read, decoded and lit by the job system too, so disk access never holds up the main thread
+            let generator = Arc::clone(&self.generator);
+            let storage = Arc::clone(&self.storage);
+
+            jobs.generate(chunk_coord, move |chunk_coord| {
+                let saved = match storage.lock().unwrap().load_chunk(chunk_coord) {
+                    Ok(saved) => saved,
+                    // Regenerating the chunk is the best that can be done
+                    Err(error) => {
+                        log::warn!("Failed to load chunk {chunk_coord}: {error:?}");
+                        None
+
+                    },
+
+                };
+
+                let mut chunk = saved.unwrap_or_else(|| generator.generate(chunk_coord));
+                chunk.mark_saved();
+
+                (chunk, generator.sky_heights(World::chunk_column(chunk_coord)))
+
+            });
+
+        }
+
+    }
+
+    fn save_chunk(&mut self, chunk_coord: IVec3, chunk: &Chunk) {
+        if let Err(error) = self.storage.lock().unwrap().save_chunk(chunk_coord, chunk) {
+            log::warn!("Failed to save chunk {chunk_coord}: {error:?}");
+
+        }
+
+    }
+
+    fn flush(&mut self) {
+        self.last_flush = Instant::now();
+
+        if let Err(error) = self.storage.lock().unwrap().flush() {
+            log::warn!("Failed to save the world: {error:?}");
+
+        }
+
+    }
+
+    /// Saves every loaded chunk that has been edited, such as when the game is closing
+    pub fn save_all(&mut self, world: &mut World) {
+        let edited: Vec<IVec3> = world.chunks()
+            .filter(|(_, chunk)| chunk.has_unsaved_edits())
+            .map(|(chunk_coord, _)| *chunk_coord)
+            .collect();
+
+        for chunk_coord in edited {
+            self.save_chunk(chunk_coord, world.chunk(chunk_coord).unwrap());
+            world.chunk_mut(chunk_coord).unwrap().mark_saved();
+
+        }
+
+        self.flush();
+
+    }
+
+    fn rebuild_load_queue(&mut self, world: &World, center: IVec3, settings: &Settings) {
+        let radius = settings.render_distance as i32;
+        let vertical_radius = settings.vertical_render_distance as i32;
+
+        self.load_queue.clear();
+
+        for z in -radius..=radius {
+            for y in -vertical_radius..=vertical_radius {
+                for x in -radius..=radius {
+                    let chunk_coord = center + IVec3::new(x, y, z);
+
+                    if Self::in_range(settings, center, chunk_coord, 0) && world.chunk(chunk_coord).is_none() && !self.evicted.contains(&chunk_coord) {
+                        self.load_queue.push(chunk_coord);
+
+                    }
+
+                }
+
+            }
+
+        }
+
+        self.load_queue.sort_unstable_by_key(|chunk_coord| -Self::distance_squared(center, *chunk_coord));
+
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec2;
+
+    use crate::block::{BlockId, BLOCK_SIZE, CHUNK_SIZE_AXIS};
+    use crate::jobs::JobResult;
+    use crate::lighting::SkyHeights;
+    use crate::registry::BlockRegistry;
+    use crate::storage::MemoryStorage;
+
+    use super::*;
+
+    // Stone below y = 0 and air above, which is much quicker to generate than real terrain
+    struct FlatGenerator;
+
+    impl TerrainGenerator for FlatGenerator {
+        fn generate(&self, chunk_coord: IVec3) -> Chunk {
+            let block = match chunk_coord.y < 0 {
+                true => BlockRegistry::global().id("stone").unwrap(),
+                false => BlockId::AIR,
+
+            };
+
+            Chunk::filled(chunk_coord, block)
+
+        }
+
+        fn sky_heights(&self, _chunk_column: IVec2) -> SkyHeights {
+            SkyHeights::filled(-1)
+
+        }
+
+    }
+
+    // Storage the test can look into after handing it to the streamer
+    #[derive(Clone, Default)]
+    struct SharedStorage(Arc<Mutex<MemoryStorage>>);
+
+    impl SharedStorage {
+        fn is_saved(&self, chunk_coord: IVec3) -> bool {
+            self.0.lock().unwrap().load_chunk(chunk_coord).unwrap().is_some()
+
+        }
+
+    }
+
+    impl WorldStorage for SharedStorage {
+        fn load_chunk(&mut self, chunk_coord: IVec3) -> anyhow::Result<Option<Chunk>> {
+            self.0.lock().unwrap().load_chunk(chunk_coord)
+
+        }
+
+        fn save_chunk(&mut self, chunk_coord: IVec3, chunk: &Chunk) -> anyhow::Result<()> {
+            self.0.lock().unwrap().save_chunk(chunk_coord, chunk)
+
+        }
+
+        fn flush(&mut self) -> anyhow::Result<()> {
+            Ok(())
+
+        }
+
+    }
+
+    struct Harness {
+        streamer: ChunkStreamer,
+        storage: SharedStorage,
+        world: World,
+        jobs: JobSystem,
+        settings: Settings,
+        // Every chunk that has been loaded or generated, in the order they arrived in
+        arrived: Vec<IVec3>,
+
+    }
+
+    impl Harness {
+        fn new() -> Self {
+            let storage = SharedStorage::default();
+
+            let settings = Settings {
+                render_distance: 2,
+                vertical_render_distance: 1,
+                save_chunks: true,
+                ..Settings::default()
+
+            };
+
+            Self {
+                streamer: ChunkStreamer::new(Arc::new(FlatGenerator), Box::new(storage.clone())),
+                storage,
+                world: World::new(),
+                jobs: JobSystem::with_workers(2),
+                settings,
+                arrived: Vec::new(),
+
+            }
+
+        }
+
+        // Streams chunks around the block coordinate until everything in range has loaded
+        fn stream_around(&mut self, block_coord: IVec3) {
+            let pos = block_coord.as_vec3() * BLOCK_SIZE;
+
+            for _ in 0..10_000 {
+                self.streamer.update(&mut self.world, &mut self.jobs, pos, &self.settings);
+
+                for result in self.jobs.poll() {
+                    if let JobResult::Generated { chunk_coord, .. } = &result {
+                        self.arrived.push(*chunk_coord);
+
+                    }
+
+                    self.world.apply_job_result(result);
+
+                }
+
+                if self.streamer.queued() == 0 && self.jobs.pending() == 0 {
+                    return;
+
+                }
+
+                std::thread::sleep(Duration::from_millis(1));
+
+            }
+
+            panic!("Chunks around {block_coord} never finished loading");
+
+        }
+
+        fn loaded(&self) -> Vec<IVec3> {
+            let mut loaded: Vec<IVec3> = self.world.chunks().map(|(chunk_coord, _)| *chunk_coord).collect();
+            loaded.sort_unstable_by_key(|chunk_coord| chunk_coord.to_array());
+
+            loaded
+
+        }
+
+    }
+
+    fn chunk_block(chunk_coord: IVec3) -> IVec3 {
+        chunk_coord * CHUNK_SIZE_AXIS as i32
+
+    }
+
+    // Far enough away that none of the chunks around the origin are in range
+    const FAR_AWAY: IVec3 = IVec3::new(CHUNK_SIZE_AXIS as i32 * 20, 0, 0);
+
+    #[test]
+    fn only_edited_chunks_are_saved() {
+        let mut harness = Harness::new();
+        harness.stream_around(IVec3::ZERO);
+
+        let edited = IVec3::new(3, 4, 5);
+        harness.world.set_block(edited, BlockRegistry::global().id("stone").unwrap());
+
+        harness.stream_around(FAR_AWAY);
+
+        let edited_chunk = World::chunk_and_local_pos(edited).0;
+        assert!(harness.storage.is_saved(edited_chunk));
+
+        for x in -2..=2 {
+            for y in -1..=1 {
+                let chunk_coord = IVec3::new(x, y, 0);
+                assert_eq!(harness.storage.is_saved(chunk_coord), chunk_coord == edited_chunk, "chunk {chunk_coord}");
+
+            }
+
+        }
+
+        // The edit comes back when the chunk is loaded again
+        harness.stream_around(IVec3::ZERO);
+        let (_, local_pos) = World::chunk_and_local_pos(edited);
+        assert_ne!(harness.world.chunk(edited_chunk).unwrap().get_block(local_pos), BlockId::AIR);
+        assert!(!harness.world.chunk(edited_chunk).unwrap().has_unsaved_edits());
+
+    }
+
+    #[test]
+    fn save_all_only_saves_edited_chunks() {
+        let mut harness = Harness::new();
+        harness.stream_around(IVec3::ZERO);
+
+        let edited = IVec3::new(-3, 2, 7);
+        harness.world.set_block(edited, BlockRegistry::global().id("stone").unwrap());
+        harness.streamer.save_all(&mut harness.world);
+
+        let edited_chunk = World::chunk_and_local_pos(edited).0;
+
+        for chunk_coord in harness.loaded() {
+            assert_eq!(harness.storage.is_saved(chunk_coord), chunk_coord == edited_chunk, "chunk {chunk_coord}");
+
+        }
+
+        assert!(harness.world.chunks().all(|(_, chunk)| !chunk.has_unsaved_edits()));
+
+    }
+
+    #[test]
+    fn chunks_are_loaded_nearest_first() {
+        let mut harness = Harness::new();
+        // One chunk at a time, so they arrive in the order they were queued
+        harness.settings.max_generating_chunks = 1;
+        harness.stream_around(IVec3::ZERO);
+
+        let distances: Vec<i32> = harness.arrived.iter().map(|chunk_coord| chunk_coord.dot(*chunk_coord)).collect();
+
+        assert_eq!(harness.arrived.len(), harness.loaded().len());
+        assert!(distances.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", harness.arrived);
+
+    }
+
+    #[test]
+    fn chunks_are_kept_for_a_chunk_past_the_render_distance() {
+        let mut harness = Harness::new();
+        harness.stream_around(IVec3::ZERO);
+
+        let behind = IVec3::new(-2, 0, 0);
+
+        // One chunk over, the chunk at the back is past the render distance but within the margin
+        harness.stream_around(chunk_block(IVec3::X));
+        assert!(harness.world.chunk(behind).is_some());
+        assert!(!ChunkStreamer::in_range(&harness.settings, IVec3::X, behind, 0));
+
+        // Moving back over the border doesn't load anything again
+        let arrived = harness.arrived.len();
+        harness.stream_around(IVec3::ZERO);
+        assert_eq!(harness.arrived.len(), arrived);
+
+        // Another chunk over, it's past the margin too
+        harness.stream_around(chunk_block(IVec3::X * 2));
+        assert!(harness.world.chunk(behind).is_none());
+
+    }
+
+    #[test]
+    fn the_farthest_chunks_are_evicted_to_stay_within_the_memory_budget() {
+        let mut harness = Harness::new();
+        harness.stream_around(IVec3::ZERO);
+
+        let in_range = harness.loaded();
+        harness.settings.chunk_memory_budget = harness.world.memory_usage() / 2;
+        harness.stream_around(IVec3::ZERO);
+
+        assert!(harness.world.memory_usage() <= harness.settings.chunk_memory_budget);
+
+        let loaded = harness.loaded();
+        let evicted: Vec<IVec3> = in_range.into_iter().filter(|chunk_coord| !loaded.contains(chunk_coord)).collect();
+        let farthest_loaded = loaded.iter().map(|chunk_coord| chunk_coord.dot(*chunk_coord)).max().unwrap();
+
+        assert!(!evicted.is_empty());
+        assert!(evicted.iter().all(|chunk_coord| chunk_coord.dot(*chunk_coord) >= farthest_loaded), "{evicted:?}");
+
+        // Moving back and forth loads the new chunks the first time, but doesn't keep regenerating the evicted ones after that
+        for _ in 0..2 {
+            harness.stream_around(chunk_block(IVec3::X));
+            harness.stream_around(IVec3::ZERO);
+
+        }
+
+        let arrived = harness.arrived.len();
+
+        for _ in 0..2 {
+            harness.stream_around(chunk_block(IVec3::X));
+            harness.stream_around(IVec3::ZERO);
+
+        }
+
+        assert_eq!(harness.arrived.len(), arrived);
+        assert!(harness.world.memory_usage() <= harness.settings.chunk_memory_budget);
+
+    }
+
+}
@@ -0,0 +1,169 @@
+//! Structures aren't written into the chunks around the one being generated, and there's no queue of writes waiting for chunks that
+//! haven't been generated yet. Instead, the world is split into cells of [`STRUCTURE_CELL_SIZE`] columns with at most one structure
+//! each, and every chunk recomputes the structures in the cells within [`MAX_STRUCTURE_RADIUS`] of it and keeps the blocks that land
+//! inside it. That costs a few extra height lookups per chunk, but chunks stay independent of each other and of generation order.
+
+use glam::IVec3;
+
+use crate::biome::Biome;
+use crate::block::BlockId;
+use crate::noise::hash_values;
+use crate::registry::BlockRegistry;
+
+/// Structures are placed at most once per cell of this many columns on each side
+pub const STRUCTURE_CELL_SIZE: i32 = 12;
+/// How many blocks a structure can reach from its anchor on the x and z axes.
+/// Anchors are kept this far from the edge of their cell, so structures in neighboring cells never overlap
+pub const MAX_STRUCTURE_RADIUS: i32 = 2;
+
+/// A block to place at a block coordinate, which only replaces air
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockWrite {
+    pub block_coord: IVec3,
+    pub block: BlockId,
+
+}
+
+/// Decorations placed on top of the terrain
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Structure {
+    Tree,
+    Boulder,
+    Hut,
+
+}
+
+// The blocks structures are made out of
+struct StructureBlocks {
+    log: BlockId,
+    leaves: BlockId,
+    planks: BlockId,
+    stone: BlockId,
+
+}
+
+impl StructureBlocks {
+    fn global() -> Self {
+        let registry = BlockRegistry::global();
+        let block = |name| registry.id(name).unwrap_or_default();
+
+        Self {
+            log: block("log"),
+            leaves: block("leaves"),
+            planks: block("planks"),
+            stone: block("stone"),
+
+        }
+
+    }
+
+}
+
+impl Structure {
+    /// Picks the structure for a cell from a number between 0 and 1, or None if the cell is left empty
+    pub fn choose(biome: Biome, roll: f32) -> Option<Self> {
+        // The chance of each structure in the biome
+        let chances: &[(Structure, f32)] = match biome {
+            Biome::Plains => &[(Structure::Tree, 0.45), (Structure::Boulder, 0.05), (Structure::Hut, 0.03)],
+            Biome::Desert => &[(Structure::Boulder, 0.05), (Structure::Hut, 0.03)],
+            Biome::Mountains => &[(Structure::Boulder, 0.2), (Structure::Tree, 0.1)],
+            Biome::Snow => &[(Structure::Tree, 0.3), (Structure::Boulder, 0.05)],
+
+        };
+
+        let mut total = 0.0;
+
+        chances.iter().find_map(|(structure, chance)| {
+            total += chance;
+            (roll < total).then_some(*structure)
+
+        })
+
+    }
+
+    /// The blocks that make up the structure, where anchor is the first air block above the ground.
+    /// seed varies the structure's shape
+    pub fn blocks(self, anchor: IVec3, seed: u64) -> Vec<BlockWrite> {
+        let blocks = StructureBlocks::global();
+        let mut writes = Vec::new();
+        let mut place = |offset: IVec3, block: BlockId| writes.push(BlockWrite { block_coord: anchor + offset, block });
+
+        match self {
+            Structure::Tree => {
+                let trunk_height = 4 + (hash_values(seed, &[0]) % 3) as i32;
+
+                // The leaves are a rounded blob around the top of the trunk, and don't replace the trunk itself
+                for y in trunk_height - 2..=trunk_height + 1 {
+                    let radius: i32 = if y > trunk_height - 1 { 1 } else { 2 };
+
+                    for z in -radius..=radius {
+                        for x in -radius..=radius {
+                            let is_corner = x.abs() == radius && z.abs() == radius;
+                            let is_trunk = x == 0 && z == 0 && y < trunk_height;
+
+                            if !is_corner && !is_trunk {
+                                place(IVec3::new(x, y, z), blocks.leaves);
+
+                            }
+
+                        }
+
+                    }
+
+                }
+
+                for y in 0..trunk_height {
+                    place(IVec3::new(0, y, 0), blocks.log);
+
+                }
+
+            },
+            Structure::Boulder => {
+                let radius = 1 + (hash_values(seed, &[0]) % 2) as i32;
+
+                for z in -radius..=radius {
+                    for y in -1..=radius {
+                        for x in -radius..=radius {
+                            if x * x + y * y + z * z <= radius * radius + 1 {
+                                place(IVec3::new(x, y, z), blocks.stone);
+
+                            }
+
+                        }
+
+                    }
+
+                }
+
+            },
+            Structure::Hut => {
+                const WALL_HEIGHT: i32 = 3;
+
+                for y in 0..=WALL_HEIGHT {
+                    for z in -2..=2_i32 {
+                        for x in -2..=2_i32 {
+                            let is_wall = x.abs() == 2 || z.abs() == 2;
+                            let is_roof = y == WALL_HEIGHT;
+                            // A gap in the south wall for a door
+                            let is_door = x == 0 && z == 2 && y < 2;
+
+                            if (is_wall || is_roof) && !is_door {
+                                place(IVec3::new(x, y, z), blocks.planks);
+
+                            }
+
+                        }
+
+                    }
+
+                }
+
+            },
+
+        };
+
+        writes
+
+    }
+
+}
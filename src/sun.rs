@@ -0,0 +1,50 @@
+use glam::Vec3;
+
+/// Sunlight, which lights each face depending on how directly it faces the sun
+#[derive(Clone, Copy, Debug)]
+pub struct Light {
+    /// The direction from the world towards the sun
+    pub sun_direction: Vec3,
+    pub color: Vec3,
+    /// How bright faces pointing away from the sun are, from 0 to 1
+    pub ambient: f32,
+
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Self {
+            // Slightly off to the side, so every side of a block comes out a different brightness
+            sun_direction: Vec3::new(0.3, 1.0, 0.5).normalize(),
+            color: Vec3::new(1.0, 0.97, 0.9),
+            ambient: 0.4,
+
+        }
+
+    }
+
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightUniform {
+    sun_direction: [f32; 3],
+    // Uniforms line vec3s up to 16 bytes
+    _padding: u32,
+    color: [f32; 3],
+    ambient: f32,
+}
+
+impl LightUniform {
+    pub fn new(light: &Light) -> Self {
+        Self {
+            sun_direction: light.sun_direction.normalize_or_zero().to_array(),
+            _padding: 0,
+            color: light.color.to_array(),
+            ambient: light.ambient,
+
+        }
+
+    }
+
+}
@@ -0,0 +1,497 @@
+use glam::{IVec2, IVec3};
+
+use crate::biome::{Biome, BiomeMap};
+use crate::block::{BlockId, Chunk, LocalPos, CHUNK_SIZE_AXIS};
+use crate::lighting::SkyHeights;
+use crate::noise::{hash_u64, hash_values, Perlin};
+use crate::ores::{place_ores, OreDistribution};
+use crate::registry::BlockRegistry;
+use crate::structures::{BlockWrite, Structure, MAX_STRUCTURE_RADIUS, STRUCTURE_CELL_SIZE};
+use crate::world::World;
+
+// How far apart the biomes that are blended together to give a column's height are, so the terrain doesn't jump at biome borders
+const BIOME_BLEND_DISTANCE: i32 = 8;
+
+/// Creates the blocks of chunks as they're loaded.
+/// Generators have to be deterministic, so the same chunk coordinate always gives the same chunk
+pub trait TerrainGenerator: Send + Sync {
+    fn generate(&self, chunk_coord: IVec3) -> Chunk;
+    /// The block y of the highest opaque block that gets generated in each column of a column of chunks
+    fn sky_heights(&self, chunk_column: IVec2) -> SkyHeights;
+
+}
+
+/// The shape of a single column of terrain
+#[derive(Clone, Copy, Debug)]
+pub struct Column {
+    pub biome: Biome,
+    /// The block height of the highest solid block
+    pub height: i32,
+
+}
+
+/// Generates layered terrain from a heightmap of layered Perlin noise, shaped by the biome of each column
+pub struct HeightmapGenerator {
+    seed: u64,
+    noise: Perlin,
+    biomes: BiomeMap,
+    // Caves are carved where both of these are close to 0, which happens along winding tunnels
+    cave_noise: [Perlin; 2],
+    /// How many blocks wide the largest hills are
+    pub scale: f64,
+    /// How many layers of noise make up the heightmap. More octaves give rougher terrain
+    pub octaves: u32,
+    /// How many blocks of soil are under the surface block
+    pub soil_depth: i32,
+    /// The block height of the unbreakable bottom of the world. Nothing is generated below it
+    pub bedrock_height: i32,
+    /// Mountains above this block height are covered in snow
+    pub snow_line: i32,
+    /// How many blocks long the bends in cave tunnels are
+    pub cave_scale: f64,
+    /// How wide cave tunnels are, from 0 for no caves to around 0.2 for very wide tunnels
+    pub cave_width: f64,
+    /// The ores that are placed in stone, in the order they're placed
+    pub ores: Vec<OreDistribution>,
+    blocks: TerrainBlocks,
+
+}
+
+// The blocks terrain is made out of
+struct TerrainBlocks {
+    grass: BlockId,
+    dirt: BlockId,
+    stone: BlockId,
+    sand: BlockId,
+    snow: BlockId,
+    bedrock: BlockId,
+
+}
+
+impl HeightmapGenerator {
+    /// Creates a generator for the given world seed, using blocks from the global registry
+    pub fn new(seed: u64) -> Self {
+        let registry = BlockRegistry::global();
+        let block = |name| registry.id(name).unwrap_or_default();
+
+        Self {
+            seed,
+            noise: Perlin::new(seed),
+            biomes: BiomeMap::new(seed),
+            cave_noise: [Perlin::new(hash_u64(seed ^ 0xCA7E)), Perlin::new(hash_u64(seed ^ 0xCA7F))],
+            scale: 96.0,
+            octaves: 4,
+            soil_depth: 3,
+            bedrock_height: -48,
+            snow_line: 32,
+            cave_scale: 40.0,
+            cave_width: 0.07,
+            ores: vec![
+                OreDistribution { block: block("coal_ore"), heights: -47..=40, veins_per_chunk: 4.0, vein_radius: 1.8 },
+                OreDistribution { block: block("iron_ore"), heights: -47..=8, veins_per_chunk: 4.0, vein_radius: 1.6 },
+                OreDistribution { block: block("gold_ore"), heights: -47..=-24, veins_per_chunk: 1.5, vein_radius: 1.3 },
+            ],
+            blocks: TerrainBlocks {
+                grass: block("grass"),
+                dirt: block("dirt"),
+                stone: block("stone"),
+                sand: block("sand"),
+                snow: block("snow"),
+                bedrock: block("bedrock"),
+
+            },
+
+        }
+
+    }
+
+    pub fn biome_map(&self) -> &BiomeMap {
+        &self.biomes
+
+    }
+
+    /// The biome and height of the column at the given block x and z
+    pub fn column(&self, x: i32, z: i32) -> Column {
+        let biome = self.biomes.biome(x, z);
+
+        // Average the shape of the nearby biomes, so the terrain slopes between them
+        let mut base_height = 0.0;
+        let mut amplitude = 0.0;
+
+        for dz in -1..=1 {
+            for dx in -1..=1 {
+                let nearby_biome = self.biomes.biome(x + dx * BIOME_BLEND_DISTANCE, z + dz * BIOME_BLEND_DISTANCE);
+                base_height += nearby_biome.base_height() / 9.0;
+                amplitude += nearby_biome.amplitude() / 9.0;
+
+            }
+
+        }
+
+        let noise = self.noise.fbm2(x as f64 / self.scale, z as f64 / self.scale, self.octaves);
+        let height = (base_height + noise * amplitude).round() as i32;
+
+        Column {
+            biome,
+            height: height.max(self.bedrock_height + 1),
+
+        }
+
+    }
+
+    /// Whether the block at the given block coordinate is carved out by a cave
+    pub fn is_cave(&self, block_coord: IVec3) -> bool {
+        let pos = block_coord.as_dvec3() / self.cave_scale;
+        // Squashing the noise vertically keeps the tunnels from being too steep to walk through
+        let [a, b] = self.cave_noise.each_ref().map(|noise| noise.noise3(pos.x, pos.y * 2.0, pos.z));
+
+        a * a + b * b < self.cave_width * self.cave_width
+
+    }
+
+    /// Places every structure that reaches into the chunk, including the ones anchored in the chunks around it.
+    /// Each chunk works out the structures around it for itself, so they come out whole no matter what order chunks are generated in
+    fn place_structures(&self, chunk_coord: IVec3, chunk: &mut Chunk) {
+        for write in self.structure_blocks(chunk_coord) {
+            let (write_chunk, local_pos) = World::chunk_and_local_pos(write.block_coord);
+
+            if write_chunk == chunk_coord && chunk.get_block(local_pos) == BlockId::AIR {
+                chunk.set_block(local_pos, write.block);
+
+            }
+
+        }
+
+    }
+
+    /// The blocks of every structure anchored close enough to the chunk to reach into it, which can also land in the chunks around it
+    pub fn structure_blocks(&self, chunk_coord: IVec3) -> Vec<BlockWrite> {
+        let chunk_size = CHUNK_SIZE_AXIS as i32;
+        let first_block = chunk_coord * chunk_size;
+        let mut writes = Vec::new();
+
+        let first_cell = (first_block / STRUCTURE_CELL_SIZE) - IVec3::ONE;
+        let last_cell = (first_block + IVec3::splat(chunk_size)) / STRUCTURE_CELL_SIZE + IVec3::ONE;
+
+        for cell_z in first_cell.z..=last_cell.z {
+            for cell_x in first_cell.x..=last_cell.x {
+                let hash = |value: i64| hash_values(self.seed, &[cell_x as i64, cell_z as i64, value]);
+
+                // Anchors are kept away from the edges of their cell, so structures in neighboring cells never overlap
+                let anchor_range = (STRUCTURE_CELL_SIZE - MAX_STRUCTURE_RADIUS * 2) as u64;
+                let x = cell_x * STRUCTURE_CELL_SIZE + MAX_STRUCTURE_RADIUS + (hash(0) % anchor_range) as i32;
+                let z = cell_z * STRUCTURE_CELL_SIZE + MAX_STRUCTURE_RADIUS + (hash(1) % anchor_range) as i32;
+
+                let column = self.column(x, z);
+                let anchor = IVec3::new(x, column.height + 1, z);
+
+                // The ground may have been carved away by a cave
+                let ground = anchor - IVec3::Y;
+
+                if self.is_cave(ground) || column.height <= self.bedrock_height {
+                    continue;
+
+                }
+
+                let roll = (hash(2) >> 40) as f32 / (1u64 << 24) as f32;
+
+                let structure = match Structure::choose(column.biome, roll) {
+                    Some(structure) => structure,
+                    None => continue,
+
+                };
+
+                writes.extend(structure.blocks(anchor, hash(3)));
+
+            }
+
+        }
+
+        writes
+
+    }
+
+    /// The block depth blocks below the surface of a column
+    fn block_at_depth(&self, column: Column, depth: i32) -> BlockId {
+        let blocks = &self.blocks;
+        let is_surface = depth == 0;
+        let is_soil = depth <= self.soil_depth;
+
+        match column.biome {
+            Biome::Plains if is_surface => blocks.grass,
+            Biome::Plains if is_soil => blocks.dirt,
+            Biome::Desert if is_soil => blocks.sand,
+            Biome::Snow if is_surface => blocks.snow,
+            Biome::Snow if is_soil => blocks.dirt,
+            Biome::Mountains if is_surface && column.height > self.snow_line => blocks.snow,
+            _ => blocks.stone,
+
+        }
+
+    }
+
+}
+
+impl TerrainGenerator for HeightmapGenerator {
+    fn generate(&self, chunk_coord: IVec3) -> Chunk {
+        let mut chunk = Chunk::new(chunk_coord);
+        let chunk_size = CHUNK_SIZE_AXIS as i32;
+        let first_block = chunk_coord * chunk_size;
+
+        for z in 0..CHUNK_SIZE_AXIS as u32 {
+            for x in 0..CHUNK_SIZE_AXIS as u32 {
+                let column = self.column(first_block.x + x as i32, first_block.z + z as i32);
+
+                // Skip straight past the parts of the column that are air in this chunk
+                let bottom = (self.bedrock_height - first_block.y).max(0);
+                let top = (column.height - first_block.y).min(chunk_size - 1);
+
+                for y in bottom..=top {
+                    let block_y = first_block.y + y;
+
+                    let block = match block_y == self.bedrock_height {
+                        true => self.blocks.bedrock,
+                        false if self.is_cave(IVec3::new(first_block.x + x as i32, block_y, first_block.z + z as i32)) => continue,
+                        false => self.block_at_depth(column, column.height - block_y),
+
+                    };
+
+                    chunk.set_block(LocalPos::new(x, y as u32, z), block);
+
+                }
+
+            }
+
+        }
+
+        place_ores(&mut chunk, chunk_coord, self.seed, &self.ores, self.blocks.stone);
+        self.place_structures(chunk_coord, &mut chunk);
+
+        chunk
+
+    }
+
+    fn sky_heights(&self, chunk_column: IVec2) -> SkyHeights {
+        let chunk_size = CHUNK_SIZE_AXIS as i32;
+        let chunk_coord = IVec3::new(chunk_column.x, 0, chunk_column.y);
+        let first_block = chunk_coord * chunk_size;
+        let mut sky_heights = SkyHeights::filled(self.bedrock_height);
+
+        for z in 0..CHUNK_SIZE_AXIS as u32 {
+            for x in 0..CHUNK_SIZE_AXIS as u32 {
+                let (block_x, block_z) = (first_block.x + x as i32, first_block.z + z as i32);
+                let mut height = self.column(block_x, block_z).height;
+
+                // Caves can carve out the top of the column, but never bedrock
+                while height > self.bedrock_height && self.is_cave(IVec3::new(block_x, height, block_z)) {
+                    height -= 1;
+
+                }
+
+                sky_heights.set(x, z, height);
+
+            }
+
+        }
+
+        // Structures stick out above the ground. Their blocks only go where there was air, so they can only raise the sky heights
+        for write in self.structure_blocks(chunk_coord) {
+            let (write_chunk, local_pos) = World::chunk_and_local_pos(write.block_coord);
+
+            if World::chunk_column(write_chunk) == chunk_column && !write.block.properties().transparent {
+                let height = sky_heights.get(local_pos.x, local_pos.z).max(write.block_coord.y);
+                sky_heights.set(local_pos.x, local_pos.z, height);
+
+            }
+
+        }
+
+        sky_heights
+
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    // Chunks around the surface at the origin, and some way off in every direction
+    const CHUNK_COORDS: [IVec3; 4] = [IVec3::ZERO, IVec3::new(0, -1, 0), IVec3::new(-3, -1, 7), IVec3::new(5, 1, -4)];
+
+    fn blocks(chunk: &Chunk) -> Vec<BlockId> {
+        chunk.blocks().collect()
+
+    }
+
+    #[test]
+    fn same_seed_gives_identical_chunks() {
+        // Separate generators, so nothing can carry over between them
+        let first = HeightmapGenerator::new(42);
+        let second = HeightmapGenerator::new(42);
+
+        for chunk_coord in CHUNK_COORDS {
+            let a = first.generate(chunk_coord);
+            let b = second.generate(chunk_coord);
+
+            assert_eq!(blocks(&a), blocks(&b), "chunk {chunk_coord}");
+            assert_eq!(blocks(&a), blocks(&first.generate(chunk_coord)), "chunk {chunk_coord} generated again");
+
+        }
+
+    }
+
+    #[test]
+    fn different_seeds_give_different_chunks() {
+        let first = HeightmapGenerator::new(42);
+        let second = HeightmapGenerator::new(43);
+
+        let differ = CHUNK_COORDS.iter().filter(|chunk_coord| blocks(&first.generate(**chunk_coord)) != blocks(&second.generate(**chunk_coord))).count();
+
+        assert!(differ > 0);
+        assert!((0..4).any(|x| first.column(x * 100, 0).height != second.column(x * 100, 0).height));
+
+    }
+
+    #[test]
+    fn nothing_is_generated_below_bedrock() {
+        let generator = HeightmapGenerator::new(42);
+
+        // The chunk with the bedrock in it, and the one below that
+        for block_y in [generator.bedrock_height, generator.bedrock_height - 1] {
+            let chunk_coord = World::chunk_and_local_pos(IVec3::new(0, block_y, 0)).0;
+            let chunk = generator.generate(chunk_coord);
+
+            for local_pos in LocalPos::all::<CHUNK_SIZE_AXIS>() {
+                let block_y = chunk_coord.y * CHUNK_SIZE_AXIS as i32 + local_pos.y as i32;
+                let block = chunk.get_block(local_pos);
+
+                match block_y.cmp(&generator.bedrock_height) {
+                    std::cmp::Ordering::Less => assert_eq!(block, BlockId::AIR),
+                    std::cmp::Ordering::Equal => assert_eq!(block, generator.blocks.bedrock),
+                    std::cmp::Ordering::Greater => (),
+
+                };
+
+            }
+
+        }
+
+    }
+
+    #[test]
+    fn structures_are_whole_across_chunk_borders() {
+        let generator = HeightmapGenerator::new(42);
+        let mut chunks: HashMap<IVec3, Chunk> = HashMap::new();
+        let mut crossing = 0;
+
+        for chunk_x in -1..=1 {
+            for chunk_z in -1..=1 {
+                let writes = generator.structure_blocks(IVec3::new(chunk_x, 0, chunk_z));
+
+                for write in writes.iter() {
+                    // Every chunk a structure lands in is generated separately, but still gets its part of the structure
+                    let (chunk_coord, local_pos) = World::chunk_and_local_pos(write.block_coord);
+                    let chunk = chunks.entry(chunk_coord).or_insert_with(|| generator.generate(chunk_coord));
+
+                    assert_ne!(chunk.get_block(local_pos), BlockId::AIR, "{write:?}");
+
+                    let touches_other_chunk = [IVec3::X, IVec3::Z].into_iter().any(|offset| {
+                        let neighbor = write.block_coord + offset;
+                        World::chunk_and_local_pos(neighbor).0 != chunk_coord && writes.iter().any(|other| other.block_coord == neighbor)
+
+                    });
+
+                    crossing += touches_other_chunk as usize;
+
+                }
+
+            }
+
+        }
+
+        assert!(crossing > 0, "No structures cross a chunk border");
+
+    }
+
+    #[test]
+    fn structures_do_not_depend_on_generation_order() {
+        let generator = HeightmapGenerator::new(42);
+
+        // Find a pair of neighboring chunks that a structure crosses the border between
+        let (first, second) = (-2..=2).flat_map(|x| (-2..=2).map(move |z| IVec3::new(x, 0, z)))
+            .flat_map(|chunk_coord| generator.structure_blocks(chunk_coord))
+            .find_map(|write| {
+                let chunk_coord = World::chunk_and_local_pos(write.block_coord).0;
+
+                [IVec3::X, IVec3::Z].into_iter()
+                    .map(|offset| World::chunk_and_local_pos(write.block_coord + offset).0)
+                    .find(|neighbor| *neighbor != chunk_coord)
+                    .map(|neighbor| (chunk_coord, neighbor))
+
+            })
+            .expect("No structures cross a chunk border");
+
+        // Fresh generators for each order, so nothing can carry over from the other one
+        let generate_in_order = |order: [IVec3; 2]| {
+            let generator = HeightmapGenerator::new(42);
+            let chunks: HashMap<IVec3, Vec<BlockId>> = order.into_iter().map(|chunk_coord| (chunk_coord, blocks(&generator.generate(chunk_coord)))).collect();
+
+            chunks
+
+        };
+
+        let forwards = generate_in_order([first, second]);
+        let backwards = generate_in_order([second, first]);
+
+        assert_eq!(forwards[&first], backwards[&first]);
+        assert_eq!(forwards[&second], backwards[&second]);
+
+    }
+
+    #[test]
+    fn sky_heights_are_the_highest_opaque_blocks() {
+        let generator = HeightmapGenerator::new(42);
+        let bottom = World::chunk_and_local_pos(IVec3::new(0, generator.bedrock_height, 0)).0.y;
+        let mut above_ground = 0;
+
+        for chunk_z in -1..=1 {
+            for chunk_x in -1..=1 {
+                let chunk_column = IVec2::new(chunk_x, chunk_z);
+                let sky_heights = generator.sky_heights(chunk_column);
+
+                // Every chunk in the column from the bedrock up, until they're empty
+                let chunks: Vec<Chunk> = (bottom..)
+                    .map(|chunk_y| generator.generate(IVec3::new(chunk_x, chunk_y, chunk_z)))
+                    .take_while(|chunk| chunk.palette() != [BlockId::AIR])
+                    .collect();
+
+                for z in 0..CHUNK_SIZE_AXIS as u32 {
+                    for x in 0..CHUNK_SIZE_AXIS as u32 {
+                        let highest = chunks.iter().enumerate()
+                            .flat_map(|(i, chunk)| (0..CHUNK_SIZE_AXIS as u32).map(move |y| (i, chunk, y)))
+                            .filter(|(_, chunk, y)| !chunk.get_block(LocalPos::new(x, *y, z)).properties().transparent)
+                            .map(|(i, _, y)| (bottom + i as i32) * CHUNK_SIZE_AXIS as i32 + y as i32)
+                            .max()
+                            .unwrap();
+
+                        assert_eq!(sky_heights.get(x, z), highest, "column {x}, {z} of {chunk_column}");
+
+                        let column = generator.column(chunk_x * CHUNK_SIZE_AXIS as i32 + x as i32, chunk_z * CHUNK_SIZE_AXIS as i32 + z as i32);
+                        above_ground += (highest > column.height) as usize;
+
+                    }
+
+                }
+
+            }
+
+        }
+
+        assert!(above_ground > 0, "No structures stick out above the ground");
+
+    }
+
+}
@@ -1,21 +1,53 @@
+use std::collections::HashMap;
+
 use image::GenericImageView;
 use anyhow::*;
 
+/// A texture view and its sampler. The view keeps the texture it was created from alive, so the texture itself isn't kept
 pub struct Texture {
-    pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
 }
 
 impl Texture {
-    pub fn from_bytes(
-        device: &wgpu::Device,
-        queue: &wgpu::Queue,
-        bytes: &[u8], 
-        label: &str
-    ) -> Result<Self> {
-        let img = image::load_from_memory(bytes)?;
-        Self::from_image(device, queue, &img, Some(label))
+    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+    /// Creates a depth texture the same size as the surface, which needs to be recreated whenever the surface is resized
+    pub fn create_depth_texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, label: &str) -> Self {
+        let size = wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: Self::DEPTH_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            }
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(
+            &wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Nearest,
+                compare: Some(wgpu::CompareFunction::LessEqual),
+                lod_min_clamp: -100.0,
+                lod_max_clamp: 100.0,
+                ..Default::default()
+            }
+        );
+
+        Self { view, sampler }
     }
 
     pub fn from_image(
@@ -75,7 +107,161 @@ impl Texture {
             }
         );
         
-        Ok(Self { texture, view, sampler })
+        Ok(Self { view, sampler })
+    }
+}
+
+// The size every texture is scaled to in the atlas
+const ATLAS_TILE_SIZE: u32 = 64;
+// How many pixels of each texture's edge are repeated around it, so filtering doesn't bleed into other textures
+const ATLAS_TILE_PADDING: u32 = 2;
+/// The most textures an atlas's UV rects can be uploaded for
+pub const MAX_ATLAS_TEXTURES: usize = 256;
+/// The name of the texture used in place of textures that couldn't be found
+pub const MISSING_TEXTURE: &str = "missing";
+
+/// Where a texture is in the atlas, in UV coordinates
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct UvRect {
+    pub min: [f32; 2],
+    pub size: [f32; 2],
+}
+
+/// Collects textures to be packed into a single texture atlas
+pub struct TextureAtlasBuilder {
+    images: Vec<(String, image::RgbaImage)>,
+}
+
+impl TextureAtlasBuilder {
+    pub fn new() -> Self {
+        Self { images: Vec::new() }
+    }
+
+    /// Adds a texture, replacing any texture that was already added with the same name
+    pub fn add_image(&mut self, name: &str, img: &image::DynamicImage) {
+        let tile = image::imageops::resize(img, ATLAS_TILE_SIZE, ATLAS_TILE_SIZE, image::imageops::FilterType::Triangle);
+
+        match self.images.iter_mut().find(|(image_name, _)| image_name == name) {
+            Some((_, image)) => *image = tile,
+            None => self.images.push((name.to_string(), tile)),
+        };
+    }
+
+    pub fn add_bytes(&mut self, name: &str, bytes: &[u8]) -> Result<()> {
+        let img = image::load_from_memory(bytes).with_context(|| format!("Couldn't decode texture {name}"))?;
+        self.add_image(name, &img);
+
+        Ok(())
+    }
+
+    /// Adds every PNG in a directory, named after its file name without the extension
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn add_dir(&mut self, dir: impl AsRef<std::path::Path>) -> Result<()> {
+        let mut paths = std::fs::read_dir(dir.as_ref())?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        // Sort the paths so the atlas is the same no matter what order the filesystem returns them in
+        paths.sort();
+
+        for path in paths.iter().filter(|path| path.extension().is_some_and(|ext| ext == "png")) {
+            let name = path.file_stem().unwrap().to_string_lossy();
+            let img = image::open(path).with_context(|| format!("Couldn't load texture {}", path.display()))?;
+
+            self.add_image(&name, &img);
+        }
+
+        Ok(())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.images.iter().any(|(image_name, _)| image_name == name)
+    }
+
+    /// Packs every texture into a grid, returning the atlas image and the UV rect of each texture
+    pub fn pack(&self) -> (image::RgbaImage, HashMap<String, UvRect>) {
+        let missing_texture = (!self.contains(MISSING_TEXTURE)).then(missing_texture);
+        let images = self.images.iter().map(|(name, image)| (name.as_str(), image))
+            .chain(missing_texture.iter().map(|image| (MISSING_TEXTURE, image)));
+
+        let image_count = self.images.len() as u32 + missing_texture.is_some() as u32;
+        let columns = (image_count as f32).sqrt().ceil() as u32;
+        let rows = image_count.div_ceil(columns);
+
+        let cell_size = ATLAS_TILE_SIZE + ATLAS_TILE_PADDING * 2;
+        let mut atlas = image::RgbaImage::new(columns * cell_size, rows * cell_size);
+        let atlas_size = [atlas.width() as f32, atlas.height() as f32];
+
+        let mut uvs = HashMap::with_capacity(image_count as usize);
+
+        for (i, (name, image)) in images.enumerate() {
+            let cell_x = (i as u32 % columns) * cell_size;
+            let cell_y = (i as u32 / columns) * cell_size;
+
+            // Copy the texture into the cell, clamping to its edge pixels in the padding
+            for y in 0..cell_size {
+                for x in 0..cell_size {
+                    let image_x = x.saturating_sub(ATLAS_TILE_PADDING).min(ATLAS_TILE_SIZE - 1);
+                    let image_y = y.saturating_sub(ATLAS_TILE_PADDING).min(ATLAS_TILE_SIZE - 1);
+
+                    atlas.put_pixel(cell_x + x, cell_y + y, *image.get_pixel(image_x, image_y));
+                }
+            }
+
+            let uv_rect = UvRect {
+                min: [
+                    (cell_x + ATLAS_TILE_PADDING) as f32 / atlas_size[0],
+                    (cell_y + ATLAS_TILE_PADDING) as f32 / atlas_size[1],
+                ],
+                size: [
+                    ATLAS_TILE_SIZE as f32 / atlas_size[0],
+                    ATLAS_TILE_SIZE as f32 / atlas_size[1],
+                ],
+            };
+
+            uvs.insert(name.to_string(), uv_rect);
+        }
+
+        (atlas, uvs)
+    }
+
+    pub fn build(&self, device: &wgpu::Device, queue: &wgpu::Queue, label: &str) -> Result<TextureAtlas> {
+        let (atlas, uvs) = self.pack();
+        let texture = Texture::from_image(device, queue, &image::DynamicImage::ImageRgba8(atlas), Some(label))?;
+
+        Ok(TextureAtlas { texture, uvs })
     }
 }
 
+// A magenta and black checkerboard, so missing textures stand out
+fn missing_texture() -> image::RgbaImage {
+    image::RgbaImage::from_fn(ATLAS_TILE_SIZE, ATLAS_TILE_SIZE, |x, y| {
+        match (x * 2 / ATLAS_TILE_SIZE + y * 2 / ATLAS_TILE_SIZE) % 2 {
+            0 => image::Rgba([255, 0, 255, 255]),
+            _ => image::Rgba([0, 0, 0, 255]),
+        }
+    })
+}
+
+/// Every block texture packed into a single texture, so they can all be drawn in one draw call
+pub struct TextureAtlas {
+    pub texture: Texture,
+    uvs: HashMap<String, UvRect>,
+}
+
+impl TextureAtlas {
+    pub fn uv_rect(&self, name: &str) -> Option<UvRect> {
+        self.uvs.get(name).copied()
+    }
+
+    /// The UV rect of each of the given textures in order, using the missing texture for any that aren't in the atlas
+    pub fn uv_rects<'a>(&self, names: impl IntoIterator<Item = &'a str>) -> Vec<UvRect> {
+        names.into_iter().map(|name| {
+            self.uv_rect(name).unwrap_or_else(|| {
+                log::warn!("Texture {name} is missing from the texture atlas");
+                self.uvs[MISSING_TEXTURE]
+            })
+        }).collect()
+    }
+}
@@ -0,0 +1,486 @@
+use std::collections::HashMap;
+
+use glam::{IVec2, IVec3, Vec3};
+
+use crate::block::{BlockId, Chunk, LocalPos, BLOCK_SIZE, CHUNK_SIZE_AXIS};
+use crate::mesh::{MeshStats, Mesher};
+use crate::jobs::{JobResult, JobSystem};
+use crate::lighting::{self, ChunkLight, SkyHeights};
+
+/// Holds every loaded chunk, keyed by its chunk coordinate
+#[derive(Default)]
+pub struct World {
+    chunks: HashMap<IVec3, Chunk>,
+    // The sky heights of every column of chunks with a chunk loaded in it
+    sky: HashMap<IVec2, SkyColumn>,
+
+}
+
+// The sky heights of a column of chunks, along with how many of its chunks are loaded so they can be forgotten when none are
+struct SkyColumn {
+    heights: SkyHeights,
+    loaded_chunks: usize,
+
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self {
+            chunks: HashMap::new(),
+            sky: HashMap::new(),
+
+        }
+
+    }
+
+    /// Creates a world with chunks from min_chunk to max_chunk (inclusive), each filled with the given block
+    pub fn with_chunks(min_chunk: IVec3, max_chunk: IVec3, block: BlockId) -> Self {
+        let mut world = Self::new();
+
+        for z in min_chunk.z..=max_chunk.z {
+            for y in min_chunk.y..=max_chunk.y {
+                for x in min_chunk.x..=max_chunk.x {
+                    let chunk_coord = IVec3::new(x, y, z);
+                    world.insert_chunk(chunk_coord, Chunk::filled(chunk_coord, block));
+
+                }
+
+            }
+
+        }
+
+        world
+
+    }
+
+    /// Adds a chunk to the world and lights it.
+    /// The chunks around it are remeshed, since it can hide the faces on their edges
+    pub fn insert_chunk(&mut self, chunk_coord: IVec3, mut chunk: Chunk) -> Option<Chunk> {
+        let sky_heights = self.guess_sky_heights(chunk_coord, &chunk);
+        lighting::light_chunk(chunk_coord, &mut chunk, &sky_heights);
+
+        self.insert_lit_chunk(chunk_coord, chunk, &sky_heights)
+
+    }
+
+    /// Adds a chunk that lighting::light_chunk has already lit by itself with the given sky heights, which should come from the terrain generator.
+    /// Only the light across its borders and in the columns where the world's sky heights turn out to be different is worked out here
+    pub fn insert_lit_chunk(&mut self, chunk_coord: IVec3, chunk: Chunk, sky_heights: &SkyHeights) -> Option<Chunk> {
+        // Columns that haven't been seen before start out with the chunk's sky heights
+        let column = self.sky.entry(Self::chunk_column(chunk_coord)).or_insert(SkyColumn { heights: sky_heights.clone(), loaded_chunks: 0 });
+        let previous = column.heights.clone();
+
+        let old_chunk = self.chunks.insert(chunk_coord, chunk);
+
+        if old_chunk.is_none() {
+            column.loaded_chunks += 1;
+
+        }
+
+        self.update_sky_heights(chunk_coord);
+        self.mark_neighbors_dirty(chunk_coord);
+        lighting::light_new_chunk(self, chunk_coord, sky_heights, &previous);
+
+        old_chunk
+
+    }
+
+    /// Removes a chunk from the world, along with the light it spread into the chunks around it.
+    /// The chunks around it are remeshed, since the faces on their edges that it hid can be seen now
+    pub fn remove_chunk(&mut self, chunk_coord: IVec3) -> Option<Chunk> {
+        let chunk = self.chunks.remove(&chunk_coord)?;
+        self.mark_neighbors_dirty(chunk_coord);
+
+        let chunk_column = Self::chunk_column(chunk_coord);
+
+        if let Some(column) = self.sky.get_mut(&chunk_column) {
+            column.loaded_chunks -= 1;
+
+            if column.loaded_chunks == 0 {
+                self.sky.remove(&chunk_column);
+
+            }
+
+        }
+
+        lighting::light_removed_chunk(self, chunk_coord, &chunk);
+
+        Some(chunk)
+
+    }
+
+    /// The x and z of a chunk coordinate, which every chunk in the same column shares
+    pub fn chunk_column(chunk_coord: IVec3) -> IVec2 {
+        IVec2::new(chunk_coord.x, chunk_coord.z)
+
+    }
+
+    /// The block y of the highest block in the column at block x and z that sky light can't get through, or None if none of its chunks are loaded.
+    /// Sky light shines on every transparent block above it
+    pub fn sky_height(&self, x: i32, z: i32) -> Option<i32> {
+        let (chunk_coord, local_pos) = World::chunk_and_local_pos(IVec3::new(x, 0, z));
+
+        self.sky.get(&Self::chunk_column(chunk_coord)).map(|column| column.heights.get(local_pos.x, local_pos.z))
+
+    }
+
+    fn set_sky_height(&mut self, x: i32, z: i32, height: i32) {
+        let (chunk_coord, local_pos) = World::chunk_and_local_pos(IVec3::new(x, 0, z));
+
+        if let Some(column) = self.sky.get_mut(&Self::chunk_column(chunk_coord)) {
+            column.heights.set(local_pos.x, local_pos.z, height);
+
+        }
+
+    }
+
+    // The block y of the highest block at or below from_y in a column that sky light can't get through.
+    // Blocks in chunks that aren't loaded count as blocking it, since they might
+    fn find_sky_height(&self, x: i32, z: i32, from_y: i32) -> i32 {
+        let mut y = from_y;
+
+        while self.get_block(IVec3::new(x, y, z)).is_some_and(|block| block.properties().transparent) {
+            y -= 1;
+
+        }
+
+        y
+
+    }
+
+    // The sky heights to light a chunk with when there's no terrain generator to give them. Columns the world already knows keep their sky heights,
+    // and the rest are blocked by the chunk's highest opaque block, or by whatever is below the chunk if it doesn't have any
+    fn guess_sky_heights(&self, chunk_coord: IVec3, chunk: &Chunk) -> SkyHeights {
+        if let Some(column) = self.sky.get(&Self::chunk_column(chunk_coord)) {
+            return column.heights.clone();
+
+        }
+
+        let bottom = chunk_coord.y * CHUNK_SIZE_AXIS as i32;
+        let mut sky_heights = SkyHeights::filled(bottom - 1);
+
+        for local_pos in LocalPos::all::<CHUNK_SIZE_AXIS>().filter(|local_pos| !chunk.get_block(*local_pos).properties().transparent) {
+            let height = bottom + local_pos.y as i32;
+            sky_heights.set(local_pos.x, local_pos.z, sky_heights.get(local_pos.x, local_pos.z).max(height));
+
+        }
+
+        sky_heights
+
+    }
+
+    // Raises the sky heights of a chunk's columns to its highest opaque blocks. Sky heights that land inside the chunk
+    // could have been wrong about its blocks, such as when they came from the generator and the chunk was edited and saved, so they're found again
+    fn update_sky_heights(&mut self, chunk_coord: IVec3) {
+        let origin = chunk_coord * CHUNK_SIZE_AXIS as i32;
+        let top = origin.y + CHUNK_SIZE_AXIS as i32 - 1;
+
+        for z in origin.z..origin.z + CHUNK_SIZE_AXIS as i32 {
+            for x in origin.x..origin.x + CHUNK_SIZE_AXIS as i32 {
+                let height = match self.sky_height(x, z) {
+                    Some(height) => height,
+                    None => continue,
+
+                };
+
+                let new_height = match height {
+                    height if height > top => height,
+                    // Finding it from the top of the chunk would go on past the chunk, and could stop at a chunk above the old sky height that isn't loaded
+                    height if height < origin.y => (origin.y..=top).rev()
+                        .find(|y| self.get_block(IVec3::new(x, *y, z)).is_some_and(|block| !block.properties().transparent))
+                        .unwrap_or(height),
+                    _ => self.find_sky_height(x, z, top),
+
+                };
+
+                self.set_sky_height(x, z, new_height);
+
+            }
+
+        }
+
+    }
+
+    // Moves the sky height of a block's column after it changed, and relights the blocks that sky light started or stopped shining on
+    fn update_sky_height(&mut self, block_coord: IVec3, block: BlockId) {
+        let old_height = match self.sky_height(block_coord.x, block_coord.z) {
+            Some(height) => height,
+            None => return,
+
+        };
+
+        let new_height = match block.properties().transparent {
+            false if block_coord.y > old_height => block_coord.y,
+            true if block_coord.y == old_height => self.find_sky_height(block_coord.x, block_coord.z, block_coord.y - 1),
+            _ => return,
+
+        };
+
+        self.set_sky_height(block_coord.x, block_coord.z, new_height);
+        lighting::sky_height_changed(self, block_coord.x, block_coord.z, old_height, new_height);
+
+    }
+
+    // Marks every chunk touching the chunk as dirty, including along its edges and at its corners
+    fn mark_neighbors_dirty(&mut self, chunk_coord: IVec3) {
+        for z in -1..=1 {
+            for y in -1..=1 {
+                for x in -1..=1 {
+                    let offset = IVec3::new(x, y, z);
+
+                    if offset != IVec3::ZERO {
+                        if let Some(neighbor) = self.chunk_mut(chunk_coord + offset) {
+                            neighbor.mark_dirty();
+
+                        }
+
+                    }
+
+                }
+
+            }
+
+        }
+
+    }
+
+    /// Marks the other chunks that any of the 26 blocks around a block are in as dirty. Their meshes read the block's light,
+    /// and whether it hides their faces or casts ambient occlusion onto them, through the layer of blocks around them
+    pub fn mark_dirty_around(&mut self, block_coord: IVec3) {
+        let (chunk_coord, local_pos) = World::chunk_and_local_pos(block_coord);
+        let last = CHUNK_SIZE_AXIS as u32 - 1;
+
+        // Which way the blocks around it spill over into other chunks on an axis
+        let spill = |local: u32| match local {
+            0 => -1..=0,
+            local if local == last => 0..=1,
+            _ => 0..=0,
+
+        };
+
+        for z in spill(local_pos.z) {
+            for y in spill(local_pos.y) {
+                for x in spill(local_pos.x) {
+                    let offset = IVec3::new(x, y, z);
+
+                    if offset != IVec3::ZERO {
+                        if let Some(chunk) = self.chunk_mut(chunk_coord + offset) {
+                            chunk.mark_dirty();
+
+                        }
+
+                    }
+
+                }
+
+            }
+
+        }
+
+    }
+
+    pub fn chunk(&self, chunk_coord: IVec3) -> Option<&Chunk> {
+        self.chunks.get(&chunk_coord)
+
+    }
+
+    pub fn chunk_mut(&mut self, chunk_coord: IVec3) -> Option<&mut Chunk> {
+        self.chunks.get_mut(&chunk_coord)
+
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = (&IVec3, &Chunk)> {
+        self.chunks.iter()
+
+    }
+
+    /// Converts a world position into the coordinate of the block containing it
+    pub fn block_coord(world_pos: Vec3) -> IVec3 {
+        // Blocks are centered on their position, so they extend half a block in each direction
+        (world_pos / BLOCK_SIZE + Vec3::splat(0.5)).floor().as_ivec3()
+
+    }
+
+    /// Splits a block coordinate into the coordinate of its chunk and its position within that chunk
+    pub fn chunk_and_local_pos(block_coord: IVec3) -> (IVec3, LocalPos) {
+        let chunk_size = CHUNK_SIZE_AXIS as i32;
+
+        let chunk_coord = IVec3::new(
+            block_coord.x.div_euclid(chunk_size),
+            block_coord.y.div_euclid(chunk_size),
+            block_coord.z.div_euclid(chunk_size),
+        );
+
+        let local_pos = IVec3::new(
+            block_coord.x.rem_euclid(chunk_size),
+            block_coord.y.rem_euclid(chunk_size),
+            block_coord.z.rem_euclid(chunk_size),
+        ).as_uvec3().into();
+
+        (chunk_coord, local_pos)
+
+    }
+
+    /// Returns the block at the given block coordinate, or None if its chunk isn't loaded
+    pub fn get_block(&self, block_coord: IVec3) -> Option<BlockId> {
+        let (chunk_coord, local_pos) = World::chunk_and_local_pos(block_coord);
+
+        self.chunk(chunk_coord).map(|chunk| chunk.get_block(local_pos))
+
+    }
+
+    /// Sets the block at the given block coordinate and updates the light around it, returning false if its chunk isn't loaded.
+    /// Neighboring chunks that touch the block, even just at an edge or corner, are remeshed too, since the block can change what they look like
+    pub fn set_block(&mut self, block_coord: IVec3, block: BlockId) -> bool {
+        let (chunk_coord, local_pos) = World::chunk_and_local_pos(block_coord);
+
+        let changed = match self.chunk_mut(chunk_coord) {
+            Some(chunk) => {
+                let changed = chunk.get_block(local_pos) != block;
+                chunk.set_block(local_pos, block);
+                changed
+
+            },
+            None => return false,
+
+        };
+
+        if changed {
+            self.mark_dirty_around(block_coord);
+            self.update_sky_height(block_coord, block);
+            lighting::update_light(self, block_coord);
+
+        }
+
+        true
+
+    }
+
+    /// Rebuilds the mesh of every chunk that has changed, returning how many were rebuilt
+    pub fn update_graphics(&mut self, mesher: Mesher, ambient_occlusion: bool) -> usize {
+        let dirty: Vec<IVec3> = self.chunks.iter()
+            .filter(|(_, chunk)| chunk.is_dirty())
+            .map(|(chunk_coord, _)| *chunk_coord)
+            .collect();
+
+        dirty.into_iter()
+            .filter(|chunk_coord| {
+                let light = ChunkLight::from_world(self, *chunk_coord).unwrap();
+                self.chunks.get_mut(chunk_coord).unwrap().update_graphics(mesher, &light, ambient_occlusion)
+
+            })
+            .count()
+
+    }
+
+    /// Queues every chunk that has changed to be meshed by the job system
+    pub fn queue_meshing(&self, jobs: &mut JobSystem, mesher: Mesher, ambient_occlusion: bool) {
+        for (chunk_coord, chunk) in self.chunks.iter() {
+            if chunk.is_dirty() && !jobs.is_meshing(*chunk_coord) {
+                let light = ChunkLight::from_world(self, *chunk_coord).unwrap();
+                jobs.mesh(*chunk_coord, chunk, light, mesher, ambient_occlusion);
+
+            }
+
+        }
+
+    }
+
+    /// Adds a finished job's output to the world
+    pub fn apply_job_result(&mut self, result: JobResult) {
+        match result {
+            JobResult::Generated { chunk_coord, chunk, sky_heights } => {
+                self.insert_lit_chunk(chunk_coord, chunk, &sky_heights);
+
+            },
+            JobResult::Meshed { chunk_coord, version, mesh } => {
+                // The chunk may have been unloaded while it was being meshed. If it was edited instead, the mesh is thrown away and it gets queued again
+                if let Some(chunk) = self.chunk_mut(chunk_coord) {
+                    chunk.set_mesh(mesh, version);
+
+                }
+
+            },
+
+        }
+
+    }
+
+    /// Roughly how many bytes every loaded chunk takes up in memory
+    pub fn memory_usage(&self) -> usize {
+        self.chunks.values().map(Chunk::memory_usage).sum()
+
+    }
+
+    /// The total size of every chunk's mesh
+    pub fn mesh_stats(&self) -> MeshStats {
+        self.chunks.values().map(|chunk| chunk.mesh().stats()).sum()
+
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::registry::BlockRegistry;
+
+    use super::*;
+
+    // Air chunks from the origin to (1, 1, 1), with every mesh built
+    fn meshed_world() -> World {
+        let mut world = World::with_chunks(IVec3::ZERO, IVec3::ONE, BlockId::AIR);
+        world.update_graphics(Mesher::Greedy, true);
+
+        assert!(world.chunks().all(|(_, chunk)| !chunk.is_dirty()));
+
+        world
+
+    }
+
+    fn dirty_chunks(world: &World) -> Vec<IVec3> {
+        let mut dirty: Vec<IVec3> = world.chunks()
+            .filter(|(_, chunk)| chunk.is_dirty())
+            .map(|(chunk_coord, _)| *chunk_coord)
+            .collect();
+
+        dirty.sort_unstable_by_key(|chunk_coord| chunk_coord.to_array());
+
+        dirty
+
+    }
+
+    #[test]
+    fn editing_a_corner_block_dirties_the_diagonal_chunks() {
+        let mut world = meshed_world();
+
+        // On the edge between the four chunks at y = 0, and not near the ones above
+        world.set_block(IVec3::new(15, 6, 15), BlockRegistry::global().id("stone").unwrap());
+
+        assert_eq!(dirty_chunks(&world), [IVec3::ZERO, IVec3::Z, IVec3::X, IVec3::new(1, 0, 1)]);
+
+    }
+
+    #[test]
+    fn editing_a_block_in_the_middle_only_dirties_its_chunk() {
+        let mut world = meshed_world();
+
+        world.set_block(IVec3::new(8, 6, 8), BlockRegistry::global().id("stone").unwrap());
+
+        assert_eq!(dirty_chunks(&world), [IVec3::ZERO]);
+
+    }
+
+    #[test]
+    fn adding_a_chunk_dirties_every_chunk_touching_it() {
+        let mut world = meshed_world();
+        world.remove_chunk(IVec3::ONE);
+        world.update_graphics(Mesher::Greedy, true);
+
+        world.insert_chunk(IVec3::ONE, Chunk::new(IVec3::ONE));
+
+        // Every other chunk touches it, at least at a corner
+        assert_eq!(dirty_chunks(&world).len(), 8);
+
+    }
+
+}